@@ -0,0 +1,57 @@
+use std::collections::{BTreeMap, HashMap};
+use crate::{Match, Result};
+
+/// How the matched names continue after the term, counted by continuation.
+#[derive(Debug, Clone)]
+pub struct Completions {
+    /// The name from the start of the match to the end of the continuation, and how many names continue like that.
+    pub groups: BTreeMap<String, usize>,
+    // for groups ending with the first digit of a number, how many digits follow it in the longest of the numbers
+    more_digits: HashMap<String, usize>,
+    chars: Option<usize>,
+}
+
+impl Completions {
+    /// Names continue by their next token, a run of letters and the separators before it, or the first digit of a
+    /// number, or by the next `chars` chars if given.
+    pub fn new(chars: Option<usize>) -> Self {
+        Completions { groups: BTreeMap::new(), more_digits: HashMap::new(), chars }
+    }
+
+    pub fn insert(&mut self, m: &Match) -> Result<()> {
+        let filename = m.file_name()?;
+        let (Some(first), Some(last)) = (m.matched.first(), m.matched.last()) else { return Ok(()) };
+        let rest = &filename[last.end..];
+        let (len, more_digits) = match self.chars {
+            Some(chars) => (rest.char_indices().nth(chars).map_or(rest.len(), |(i, _)| i), 0),
+            None => {
+                let len = next_token_len(rest);
+                let start = rest.find(char::is_alphanumeric).unwrap_or(len);
+                match rest[start..len].bytes().all(|b| b.is_ascii_digit()) {
+                    true if len - start > 1 => (start + 1, len - start - 1),
+                    _ => (len, 0),
+                }
+            }
+        };
+        let group = filename[first.start..last.end + len].to_string();
+        if more_digits > 0 {
+            let max = self.more_digits.entry(group.clone()).or_default();
+            *max = (*max).max(more_digits);
+        }
+        *self.groups.entry(group).or_default() += 1;
+        Ok(())
+    }
+
+    /// The group with an `x` for every digit following it, e.g. `ABC-1xx` for `ABC-1`.
+    pub fn label(&self, group: &str) -> String {
+        format!("{group}{}", "x".repeat(self.more_digits.get(group).copied().unwrap_or(0)))
+    }
+}
+
+// the separators and the run of digits or letters after them
+pub(crate) fn next_token_len(s: &str) -> usize {
+    let start = s.find(char::is_alphanumeric).unwrap_or(s.len());
+    let Some(first) = s[start..].chars().next() else { return s.len() };
+    let is_digit = first.is_ascii_digit();
+    s[start..].find(|c: char| !c.is_alphanumeric() || c.is_ascii_digit() != is_digit).map_or(s.len(), |len| start + len)
+}
@@ -0,0 +1,185 @@
+use std::{collections::{HashMap, HashSet}, fs, io::{BufRead, BufReader, BufWriter, ErrorKind, Write}, ops::ControlFlow, os::unix::net::{UnixListener, UnixStream}, path::PathBuf, sync::{Arc, RwLock}, thread};
+use serde::{Deserialize, Serialize};
+use crate::{apply_changes, Config, Error, Index, Match, MatchOptions, Result, Searcher, WalkOptions, Watcher};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Request {
+    pub category: String,
+    pub terms: Vec<String>,
+    pub options: MatchOptions,
+    // the category as the client knows it, which the daemon may not if the config changed since it started
+    pub dirs: Vec<String>,
+    pub walk: WalkOptions,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+enum Response {
+    Match(Match),
+    Done,
+    // the daemon doesn't know the category or lists other files for it
+    Stale,
+    Error(String),
+}
+
+pub fn socket_path() -> Result<PathBuf> {
+    let dir = dirs::runtime_dir().or_else(dirs::cache_dir).ok_or(Error::CacheDirNotFound)?;
+    Ok(dir.join(format!("{}.sock", env!("CARGO_PKG_NAME"))))
+}
+
+/// Keeps the listings of all categories in memory and answers searches over a Unix socket.
+pub struct Daemon {
+    config: Arc<Config>,
+    indexes: Arc<RwLock<HashMap<String, Index>>>,
+}
+
+impl Daemon {
+    pub fn new(config: Config) -> Result<Self> {
+        let mut indexes = HashMap::new();
+        for (name, category) in &config.categories {
+            indexes.insert(name.clone(), Index::load_or_build(name, category)?);
+        }
+        Ok(Daemon { config: Arc::new(config), indexes: Arc::new(RwLock::new(indexes)) })
+    }
+
+    pub fn run(self) -> Result<()> {
+        let path = socket_path()?;
+        if path.exists() {
+            if UnixStream::connect(&path).is_ok() {
+                return Err(Error::DaemonAlreadyRunning(path));
+            }
+            fs::remove_file(&path)?;
+        }
+        let listener = UnixListener::bind(&path)?;
+        log::info!("Listening on {}", path.display());
+
+        let watcher = Watcher::new(self.config.categories.iter().map(|(name, category)| (name.as_str(), category)))?;
+        let (config, indexes) = (self.config.clone(), self.indexes.clone());
+        thread::spawn(move || {
+            if let Err(e) = update_indexes(watcher, &config, &indexes) {
+                log::error!("Indexes are not updated anymore: {}", e);
+            }
+        });
+
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let (config, indexes) = (self.config.clone(), self.indexes.clone());
+            thread::spawn(move || {
+                if let Err(e) = serve(stream, &config, &indexes) {
+                    log::warn!("Failed to serve a request: {}", e);
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+fn update_indexes(watcher: Watcher, config: &Config, indexes: &RwLock<HashMap<String, Index>>) -> Result<()> {
+    loop {
+        let changes = watcher.recv()?;
+        // rebuilding walks the whole category, so searches go on with the old index meanwhile
+        let mut rebuilt = HashMap::new();
+        for (name, change) in &changes {
+            if Index::needs_rebuild(change) && !rebuilt.contains_key(name) {
+                rebuilt.insert(name.clone(), Index::build(config.category(name)?)?);
+            }
+        }
+        let changes = changes.into_iter().filter(|(name, _)| !rebuilt.contains_key(name)).collect();
+        let mut changed = rebuilt.keys().cloned().collect::<HashSet<_>>();
+        {
+            let mut indexes = indexes.write().unwrap();
+            changed.extend(apply_changes(changes, &mut indexes, config)?);
+            indexes.extend(rebuilt);
+        }
+        let indexes = indexes.read().unwrap();
+        for name in changed {
+            indexes[&name].save(&name)?;
+            log::info!("Updated index of {}", name);
+        }
+    }
+}
+
+fn serve(stream: UnixStream, config: &Config, indexes: &RwLock<HashMap<String, Index>>) -> Result<()> {
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let request: Request = serde_json::from_str(&line)?;
+    log::debug!("Request: {:?}", request);
+
+    // don't hold the lock while writing to a possibly slow client
+    let result = find_matches(request, config, indexes);
+
+    let mut writer = BufWriter::new(&stream);
+    match result {
+        Ok(None) => write_response(&mut writer, &Response::Stale)?,
+        Ok(Some(matches)) => {
+            for m in matches {
+                write_response(&mut writer, &Response::Match(m))?;
+            }
+            write_response(&mut writer, &Response::Done)?;
+        }
+        Err(e) => write_response(&mut writer, &Response::Error(e.to_string()))?,
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// `None` if the category isn't known as the client knows it
+fn find_matches(request: Request, config: &Config, indexes: &RwLock<HashMap<String, Index>>) -> Result<Option<Vec<Match>>> {
+    let Ok(category) = config.category(&request.category) else { return Ok(None) };
+    let indexes = indexes.read().unwrap();
+    let Some(index) = indexes.get(&request.category).filter(|index| index.lists(&request.dirs, &request.walk)) else { return Ok(None) };
+    let mut matches = Vec::new();
+    Searcher::with_options(category, request.options, request.terms)?.search_index(index, |m| {
+        matches.push(m);
+        Ok::<_, Error>(ControlFlow::Continue(()))
+    })?;
+    Ok(Some(matches))
+}
+
+fn write_response(writer: &mut impl Write, response: &Response) -> Result<()> {
+    serde_json::to_writer(&mut *writer, response)?;
+    writeln!(writer)?;
+    Ok(())
+}
+
+/// Connection to a running daemon.
+pub struct Client {
+    stream: UnixStream,
+}
+
+impl Client {
+    /// Returns `None` if no daemon is running.
+    pub fn connect() -> Result<Option<Self>> {
+        match UnixStream::connect(socket_path()?) {
+            Ok(stream) => Ok(Some(Client { stream })),
+            Err(e) if matches!(e.kind(), ErrorKind::NotFound | ErrorKind::ConnectionRefused) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Same as `Searcher::search`, but answered by the daemon. Returns `false` without calling `f` if the daemon
+    /// doesn't know the category as the request does, e.g. because the config changed since it started.
+    pub fn search<F, E>(self, request: &Request, mut f: F) -> std::result::Result<bool, E>
+    where
+        F: FnMut(Match) -> std::result::Result<ControlFlow<()>, E>,
+        E: From<Error>,
+    {
+        let mut writer = &self.stream;
+        serde_json::to_writer(&mut writer, request).map_err(Error::from)?;
+        writeln!(writer).map_err(Error::from)?;
+
+        for line in BufReader::new(&self.stream).lines() {
+            let line = line.map_err(Error::from)?;
+            match serde_json::from_str(&line).map_err(Error::from)? {
+                Response::Match(m) => {
+                    if f(m)?.is_break() {
+                        return Ok(true);
+                    }
+                }
+                Response::Done => return Ok(true),
+                Response::Stale => return Ok(false),
+                Response::Error(message) => return Err(Error::Daemon(message).into()),
+            }
+        }
+        Err(Error::Daemon("connection closed before the search was done".to_string()).into())
+    }
+}
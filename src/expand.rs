@@ -0,0 +1,115 @@
+use crate::{Error, Result};
+
+/// The most terms a single search term may expand into.
+const MAX_TERMS: usize = 100_000;
+
+/// Expands `{a,b}` alternatives and `100..120` numeric ranges (also as `{100..120}`) into individual terms, e.g.
+/// `ABC-1{0,1}5` into `ABC-105` and `ABC-115`. Numbers keep the zero padding of the shorter bound. Braces without a
+/// comma or range in them, like `{tag}`, are kept as they are.
+pub fn expand(term: &str) -> Result<Vec<String>> {
+    let mut expanded = Vec::new();
+    expand_into(term, term, &mut expanded)?;
+    Ok(expanded)
+}
+
+fn expand_into(original: &str, term: &str, expanded: &mut Vec<String>) -> Result<()> {
+    let Some((before, alternatives, after)) = split_first(term) else {
+        if expanded.len() == MAX_TERMS {
+            return Err(Error::TooManyTerms(original.to_string()));
+        }
+        expanded.push(term.to_string());
+        return Ok(());
+    };
+    for alternative in alternatives {
+        expand_into(original, &format!("{before}{alternative}{after}"), expanded)?;
+    }
+    Ok(())
+}
+
+// the part before the first brace group or range, what that expands into, and the part after it
+fn split_first(term: &str) -> Option<(&str, Vec<String>, &str)> {
+    let bytes = term.as_bytes();
+    for i in 0..bytes.len() {
+        if bytes[i] == b'{' {
+            let Some(len) = term[i + 1..].find(['{', '}']) else { continue };
+            let (body, end) = (&term[i + 1..i + 1 + len], i + 1 + len);
+            if bytes[end] != b'}' {
+                continue;
+            }
+            let alternatives = match body.split_once("..") {
+                _ if body.contains(',') => body.split(',').map(String::from).collect(),
+                Some((start, end)) if is_number(start) && is_number(end) => numbers(start, end)?,
+                _ => continue,
+            };
+            return Some((&term[..i], alternatives, &term[end + 1..]));
+        }
+        if bytes[i..].starts_with(b"..") && i > 0 && bytes[i - 1].is_ascii_digit() {
+            let start = term[..i].rfind(|c: char| !c.is_ascii_digit()).map_or(0, |j| j + 1);
+            let end = term[i + 2..].find(|c: char| !c.is_ascii_digit()).map_or(term.len(), |j| i + 2 + j);
+            if end == i + 2 {
+                continue;
+            }
+            return Some((&term[..start], numbers(&term[start..i], &term[i + 2..end])?, &term[end..]));
+        }
+    }
+    None
+}
+
+fn is_number(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())
+}
+
+// counts down if the range is reversed
+fn numbers(start: &str, end: &str) -> Option<Vec<String>> {
+    let (first, last) = (start.parse::<u64>().ok()?, end.parse::<u64>().ok()?);
+    let width = start.len().min(end.len());
+    let numbers: Box<dyn Iterator<Item = u64>> = if first <= last { Box::new(first..=last) } else { Box::new((last..=first).rev()) };
+    // one more than allowed, so that the expansion fails instead of being cut short
+    Some(numbers.take(MAX_TERMS + 1).map(|n| format!("{n:0width$}")).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expanded(term: &str) -> Vec<String> {
+        expand(term).unwrap()
+    }
+
+    #[test]
+    fn multibyte_terms() {
+        assert_eq!(expanded("あいう"), ["あいう"]);
+        assert_eq!(expanded("ＡＢＣ-1..2"), ["ＡＢＣ-1", "ＡＢＣ-2"]);
+        assert_eq!(expanded("第{1,2}話"), ["第1話", "第2話"]);
+        assert_eq!(expanded("é..1"), ["é..1"]);
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(expanded("ABC-8..10"), ["ABC-8", "ABC-9", "ABC-10"]);
+        assert_eq!(expanded("EP{08..10}"), ["EP08", "EP09", "EP10"]);
+        assert_eq!(expanded("EP3..1"), ["EP3", "EP2", "EP1"]);
+        assert_eq!(expanded("a..b"), ["a..b"]);
+        assert_eq!(expanded("1.."), ["1.."]);
+    }
+
+    #[test]
+    fn braces() {
+        assert_eq!(expanded("ABC-1{0,1}5"), ["ABC-105", "ABC-115"]);
+        assert_eq!(expanded("{a,b}{1..2}"), ["a1", "a2", "b1", "b2"]);
+        assert_eq!(expanded("x{,y}"), ["x", "xy"]);
+    }
+
+    #[test]
+    fn tags_are_kept() {
+        assert_eq!(expanded("{tag}"), ["{tag}"]);
+        assert_eq!(expanded("{tag} {a,b}"), ["{tag} a", "{tag} b"]);
+        assert_eq!(expanded("{open"), ["{open"]);
+    }
+
+    #[test]
+    fn too_many_terms() {
+        assert!(matches!(expand("0..100000"), Err(Error::TooManyTerms(_))));
+        assert_eq!(expanded("1..100000").len(), MAX_TERMS);
+    }
+}
@@ -0,0 +1,148 @@
+use std::ops::Range;
+use unicode_normalization::{char::canonical_combining_class, UnicodeNormalization};
+use crate::{IdNormalization, MatchOptions, Normalization};
+
+/// A string folded for comparison, remembering where each of its bytes came from in the original string.
+#[derive(Debug, Clone)]
+pub struct Folded {
+    pub text: String,
+    origins: Vec<Range<usize>>,
+    original_len: usize,
+}
+
+impl Folded {
+    pub fn new(s: &str, options: &MatchOptions) -> Self {
+        let mut folded = Folded { text: String::with_capacity(s.len()), origins: Vec::with_capacity(s.len()), original_len: s.len() };
+        let mut chars = Vec::new();
+        for (start, segment) in segments(s, options) {
+            let origin = start..start + segment.len();
+            chars.clear();
+            if options.japanese {
+                chars.extend(segment.chars().map(fold_width));
+            } else {
+                chars.extend(segment.chars());
+            }
+            chars = match options.normalize {
+                // half-width voiced sound marks are combining marks now, compose them with their kana
+                Normalization::None if options.japanese => chars.drain(..).nfc().collect(),
+                Normalization::None => chars,
+                Normalization::Nfc => chars.drain(..).nfc().collect(),
+                Normalization::Nfkc => chars.drain(..).nfkc().collect(),
+            };
+            for &c in &chars {
+                let c = if options.japanese { fold_kana(c) } else { c };
+                if options.ignore_case {
+                    for c in c.to_lowercase() {
+                        folded.push(c, origin.clone());
+                    }
+                } else {
+                    folded.push(c, origin.clone());
+                }
+            }
+        }
+        match &options.id {
+            Some(id) => folded.normalize_id(id),
+            None => folded,
+        }
+    }
+
+    // drops separators and the leading zeros of numbers, and folds ASCII letter case
+    fn normalize_id(self, id: &IdNormalization) -> Self {
+        let mut normalized = Folded { text: String::with_capacity(self.text.len()), origins: Vec::with_capacity(self.text.len()), original_len: self.original_len };
+        let mut chars = self.text.char_indices().peekable();
+        let mut in_number = false;
+        while let Some((i, c)) = chars.next() {
+            if id.separators.contains(c) {
+                in_number = false;
+                continue;
+            }
+            let is_leading_zero = c == '0' && !in_number && chars.peek().is_some_and(|(_, next)| next.is_ascii_digit());
+            in_number = c.is_ascii_digit() && !is_leading_zero;
+            if !is_leading_zero {
+                normalized.push(c.to_ascii_lowercase(), self.origins[i].clone());
+            }
+        }
+        normalized
+    }
+
+    fn push(&mut self, c: char, origin: Range<usize>) {
+        self.text.push(c);
+        self.origins.resize(self.text.len(), origin);
+    }
+
+    /// Maps a byte range of the folded text back onto the original string.
+    pub fn origin(&self, range: Range<usize>) -> Range<usize> {
+        let start = self.origins.get(range.start).map_or(self.original_len, |origin| origin.start);
+        if range.is_empty() {
+            return start..start;
+        }
+        start..self.origins[range.end - 1].end
+    }
+}
+
+// Splits the string into the smallest pieces that normalize independently of each other: a starter and the
+// combining marks following it. Without normalization every char is its own piece.
+fn segments<'s>(s: &'s str, options: &MatchOptions) -> Vec<(usize, &'s str)> {
+    let mut segments: Vec<(usize, &str)> = Vec::new();
+    for (i, c) in s.char_indices() {
+        let continues = options.composes()
+            && (canonical_combining_class(c) != 0 || is_hangul_vowel_or_trailing(c) || (options.japanese && is_halfwidth_sound_mark(c)));
+        match segments.last_mut() {
+            Some((start, segment)) if continues => *segment = &s[*start..i + c.len_utf8()],
+            _ => segments.push((i, &s[i..i + c.len_utf8()])),
+        }
+    }
+    segments
+}
+
+// conjoining jamo compose with the preceding leading consonant even though they are starters
+fn is_hangul_vowel_or_trailing(c: char) -> bool {
+    ('\u{1160}'..='\u{11FF}').contains(&c)
+}
+
+fn is_halfwidth_sound_mark(c: char) -> bool {
+    c == '\u{FF9E}' || c == '\u{FF9F}'
+}
+
+const HALFWIDTH_KATAKANA: [char; 61] = [
+    '。', '「', '」', '、', '・', 'ヲ', 'ァ', 'ィ', 'ゥ', 'ェ', 'ォ', 'ャ', 'ュ', 'ョ', 'ッ', 'ー', 'ア', 'イ', 'ウ', 'エ', 'オ',
+    'カ', 'キ', 'ク', 'ケ', 'コ', 'サ', 'シ', 'ス', 'セ', 'ソ', 'タ', 'チ', 'ツ', 'テ', 'ト', 'ナ', 'ニ', 'ヌ', 'ネ', 'ノ',
+    'ハ', 'ヒ', 'フ', 'ヘ', 'ホ', 'マ', 'ミ', 'ム', 'メ', 'モ', 'ヤ', 'ユ', 'ヨ', 'ラ', 'リ', 'ル', 'レ', 'ロ', 'ワ', 'ン',
+];
+
+// full-width ASCII to ASCII, half-width katakana to full-width, half-width sound marks to combining ones
+fn fold_width(c: char) -> char {
+    match c {
+        '\u{3000}' => ' ',
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+        '\u{FF61}'..='\u{FF9D}' => HALFWIDTH_KATAKANA[(c as u32 - 0xFF61) as usize],
+        '\u{FF9E}' => '\u{3099}',
+        '\u{FF9F}' => '\u{309A}',
+        _ => c,
+    }
+}
+
+// katakana to hiragana
+fn fold_kana(c: char) -> char {
+    match c {
+        '\u{30A1}'..='\u{30F6}' => char::from_u32(c as u32 - 0x60).unwrap_or(c),
+        '\u{30FD}' | '\u{30FE}' => char::from_u32(c as u32 - 0x60).unwrap_or(c),
+        _ => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids() {
+        let options = MatchOptions { id: Some(IdNormalization::default()), ..Default::default() };
+        for name in ["ABC-123", "ABC_123", "ABC-00123", "abc123", "Abc 0123"] {
+            assert_eq!(Folded::new(name, &options).text, "abc123", "{name}");
+        }
+        let folded = Folded::new("abc-007", &options);
+        assert_eq!(folded.text, "abc7");
+        assert_eq!(folded.origin(0..4), 0..7);
+    }
+}
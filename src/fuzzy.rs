@@ -0,0 +1,60 @@
+use std::ops::Range;
+
+const SCORE_MATCH: u32 = 16;
+const BONUS_CONSECUTIVE: u32 = 12;
+const BONUS_WORD_START: u32 = 8;
+const BONUS_FIRST_CHAR: u32 = 8;
+const PENALTY_GAP: u32 = 2;
+
+/// Finds the chars of `pattern` in order in `text`. Returns the score, higher for tighter matches and matches at word
+/// starts, and the byte ranges of the chars of `text` that were matched.
+pub fn fuzzy_match(text: &str, pattern: &str) -> Option<(u32, Vec<Range<usize>>)> {
+    let chars = text.char_indices().collect::<Vec<_>>();
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    if pattern.is_empty() {
+        return Some((0, Vec::new()));
+    }
+
+    // the earliest end of a match
+    let mut n_matched = 0;
+    let end = chars.iter().position(|&(_, c)| {
+        if c == pattern[n_matched] {
+            n_matched += 1;
+        }
+        n_matched == pattern.len()
+    })?;
+    // the latest start of a match ending there, which makes the match as tight as possible
+    let mut n_matched = pattern.len();
+    let start = (0..=end).rev().find(|&i| {
+        if chars[i].1 == pattern[n_matched - 1] {
+            n_matched -= 1;
+        }
+        n_matched == 0
+    })?;
+
+    let mut positions = Vec::with_capacity(pattern.len());
+    for (i, &(_, c)) in chars.iter().enumerate().take(end + 1).skip(start) {
+        if positions.len() < pattern.len() && c == pattern[positions.len()] {
+            positions.push(i);
+        }
+    }
+
+    let mut score = 0;
+    for (n, &i) in positions.iter().enumerate() {
+        score += SCORE_MATCH;
+        if n > 0 && positions[n - 1] + 1 == i {
+            score += BONUS_CONSECUTIVE;
+        }
+        if i == 0 || !chars[i - 1].1.is_alphanumeric() {
+            score += BONUS_WORD_START;
+        }
+    }
+    if start == 0 {
+        score += BONUS_FIRST_CHAR;
+    }
+    let n_gaps = (end + 1 - start - positions.len()) as u32;
+    score = score.saturating_sub(n_gaps * PENALTY_GAP);
+
+    let ranges = positions.into_iter().map(|i| chars[i].0..chars[i].0 + chars[i].1.len_utf8()).collect();
+    Some((score, ranges))
+}
@@ -0,0 +1,149 @@
+use std::{fs::{self, File}, io::{BufReader, BufWriter}, ops::Range, path::{Path, PathBuf}};
+use serde::{Deserialize, Serialize};
+use crate::{file_name, watch::Change, CategoryConfig, Error, Result, WalkOptions, Walker, IGNORE_FILE_NAME};
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+pub struct Entry {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+/// Sorted filename listing of a category, stored under the XDG cache dir.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Index {
+    dirs: Vec<String>,
+    walk: WalkOptions,
+    entries: Vec<Entry>,
+}
+
+impl Index {
+    pub fn build(category: &CategoryConfig) -> Result<Self> {
+        let mut entries = Vec::new();
+        for path in Walker::new(category)?.walk() {
+            entries.push(Entry { name: file_name(&path)?, path });
+        }
+        entries.sort();
+        Ok(Index { dirs: category.dirs.clone(), walk: category.walk.clone(), entries })
+    }
+
+    pub fn file_path(category_name: &str) -> Result<PathBuf> {
+        let cache_dir = dirs::cache_dir().ok_or(Error::CacheDirNotFound)?;
+        Ok(cache_dir.join(env!("CARGO_PKG_NAME")).join(format!("{category_name}.index")))
+    }
+
+    /// Loads the index of the category, or `None` if it was never built, can't be read by this version, or the category
+    /// dirs or how they are walked have changed since.
+    pub fn load(category_name: &str, category: &CategoryConfig) -> Result<Option<Self>> {
+        let path = Self::file_path(category_name)?;
+        if !path.exists() {
+            log::debug!("No index found at {}", path.display());
+            return Ok(None);
+        }
+        let reader = BufReader::new(File::open(&path)?);
+        let index: Index = match bincode::deserialize_from(reader) {
+            Ok(index) => index,
+            Err(e) => {
+                log::warn!("Ignoring unreadable index of {category_name}: {e}");
+                return Ok(None);
+            }
+        };
+        if !index.lists(&category.dirs, &category.walk) {
+            log::warn!("Ignoring stale index of {category_name}, its dirs or walk options differ from the config");
+            return Ok(None);
+        }
+        log::debug!("Loaded index with {} entries from {}", index.entries.len(), path.display());
+        Ok(Some(index))
+    }
+
+    /// Whether the index lists the files of the dirs walked that way.
+    pub fn lists(&self, dirs: &[String], walk: &WalkOptions) -> bool {
+        self.dirs == dirs && self.walk.walks_same(walk)
+    }
+
+    pub fn load_or_build(category_name: &str, category: &CategoryConfig) -> Result<Self> {
+        if let Some(index) = Self::load(category_name, category)? {
+            return Ok(index);
+        }
+        let index = Self::build(category)?;
+        index.save(category_name)?;
+        Ok(index)
+    }
+
+    pub fn save(&self, category_name: &str) -> Result<()> {
+        let path = Self::file_path(category_name)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp_path = path.with_extension("index.tmp");
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        bincode::serialize_into(&mut writer, self)?;
+        writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// Adds the path, and everything under it if it's a dir, unless the category excludes it. Returns whether
+    /// anything was added.
+    pub fn insert(&mut self, path: &Path, category: &CategoryConfig) -> Result<bool> {
+        let mut inserted = false;
+        for path in Walker::new(category)?.walk_path(path) {
+            inserted |= self.insert_entry(Entry { name: file_name(&path)?, path });
+        }
+        Ok(inserted)
+    }
+
+    fn insert_entry(&mut self, entry: Entry) -> bool {
+        match self.entries.binary_search(&entry) {
+            Ok(_) => false,
+            Err(i) => {
+                self.entries.insert(i, entry);
+                true
+            }
+        }
+    }
+
+    /// Removes the path and everything under it. Returns whether anything was removed.
+    pub fn remove(&mut self, path: &Path) -> bool {
+        let len = self.entries.len();
+        self.entries.retain(|entry| !entry.path.starts_with(path));
+        self.entries.len() != len
+    }
+
+    pub fn apply(&mut self, change: &Change, category: &CategoryConfig) -> Result<bool> {
+        match change {
+            _ if Self::needs_rebuild(change) => {
+                *self = Self::build(category)?;
+                Ok(true)
+            }
+            Change::Added(path) => self.insert(path, category),
+            Change::Removed(path) => Ok(self.remove(path)),
+            Change::Rescan => unreachable!(),
+        }
+    }
+
+    /// Whether the change can only be applied by building the index again.
+    pub fn needs_rebuild(change: &Change) -> bool {
+        // what is ignored may have changed
+        let is_ignore_file = change.path().is_some_and(|path| path.file_name().is_some_and(|name| name == IGNORE_FILE_NAME));
+        is_ignore_file || matches!(change, Change::Rescan)
+    }
+
+    /// Range of the entries whose names start with `prefix`.
+    pub fn prefix_range(&self, prefix: &str) -> Range<usize> {
+        let start = self.entries.partition_point(|entry| entry.name.as_str() < prefix);
+        let len = self.entries[start..].partition_point(|entry| entry.name.starts_with(prefix));
+        start..start + len
+    }
+}
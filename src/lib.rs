@@ -0,0 +1,99 @@
+use std::{collections::HashMap, path::{Path, PathBuf}};
+use serde::{Deserialize, Serialize};
+
+mod complete;
+mod daemon;
+mod expand;
+mod fold;
+mod fuzzy;
+mod index;
+mod options;
+mod pattern;
+mod query;
+mod search;
+mod series;
+mod stats;
+mod strip;
+mod walk;
+mod watch;
+
+pub use complete::Completions;
+pub use daemon::{socket_path, Client, Daemon, Request};
+pub use fold::Folded;
+pub use index::{Entry, Index};
+pub use options::{FileType, IdNormalization, MatchOptions, Mode, Normalization, Syntax};
+pub use query::{parse_duration, parse_size, Comparison, Filter, Predicate};
+pub use search::{Match, Searcher};
+pub use series::Series;
+pub use stats::{DirStats, Stats};
+pub use strip::StripRules;
+pub use walk::{Walk, WalkOptions, Walker, IGNORE_FILE_NAME};
+pub use watch::{apply_changes, Change, Watcher};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Search category not found: {0}")]
+    CategoryNotFound(String),
+    #[error("Could not get file name for path: {0}")]
+    CouldntGetFileName(PathBuf),
+    #[error("Could not find the cache dir")]
+    CacheDirNotFound,
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Broken index: {0}")]
+    BrokenIndex(#[from] bincode::Error),
+    #[error("Could not watch dirs: {0}")]
+    Watch(#[from] notify::Error),
+    #[error("Filesystem watcher stopped unexpectedly")]
+    WatcherStopped,
+    #[error("Broken message: {0}")]
+    BrokenMessage(#[from] serde_json::Error),
+    #[error("Daemon is already running on {0}")]
+    DaemonAlreadyRunning(PathBuf),
+    #[error("Daemon error: {0}")]
+    Daemon(String),
+    #[error("Unknown normalization form: {0} (expected none, nfc or nfkc)")]
+    UnknownNormalization(String),
+    #[error("Unknown match mode: {0} (expected prefix, contains, suffix, exact or fuzzy)")]
+    UnknownMode(String),
+    #[error("Unknown term syntax: {0} (expected literal, glob or regex)")]
+    UnknownSyntax(String),
+    #[error("Unknown file type: {0} (expected file or dir)")]
+    UnknownFileType(String),
+    #[error("Invalid filter {0}: {1}")]
+    InvalidFilter(String, String),
+    #[error("{0} terms can't be used in {1} mode")]
+    UnsupportedSyntax(Syntax, Mode),
+    #[error("Search term expands into too many terms: {0}")]
+    TooManyTerms(String),
+    #[error("Invalid regex: {0}")]
+    InvalidRegex(#[from] regex::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Config {
+    #[serde(flatten)]
+    pub categories: HashMap<String, CategoryConfig>,
+}
+
+impl Config {
+    pub fn category(&self, name: &str) -> Result<&CategoryConfig> {
+        self.categories.get(name).ok_or_else(|| Error::CategoryNotFound(name.to_string()))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CategoryConfig {
+    pub dirs: Vec<String>,
+    #[serde(flatten)]
+    pub options: MatchOptions,
+    #[serde(flatten)]
+    pub walk: WalkOptions,
+}
+
+fn file_name(path: &Path) -> Result<String> {
+    let filename = path.file_name().ok_or_else(|| Error::CouldntGetFileName(path.to_path_buf()))?;
+    Ok(filename.to_string_lossy().into_owned())
+}
@@ -1,41 +1,117 @@
-use std::{collections::{HashMap, HashSet}, path::PathBuf, process::exit, io::Write};
+use std::{collections::{HashMap, HashSet}, ops::ControlFlow, process::exit, io::Write};
 use anyhow::Result;
-use serde::{Deserialize, Serialize};
-use clap::{crate_name, Parser};
+use clap::{crate_name, error::ErrorKind, Args, Parser, Subcommand};
 use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
+use prefix_search::{apply_changes, Client, Completions, Config, Daemon, FileType, IdNormalization, Index, Match, Mode, Normalization, Request, Searcher, Series, Stats, Syntax, Watcher};
 
-#[derive(Debug, thiserror::Error)]
-enum Error {
-    #[error("Search category not found: {0}")]
-    CategoryNotFound(String),
-    #[error("Could not get file name for path: {0}")]
-    CouldntGetFileName(PathBuf),
-}
-
-#[derive(Debug, Deserialize, Serialize)]
-struct Config {
-    #[serde(flatten)]
-    categories: HashMap<String, CategoryConfig>,
-}
-
-impl Default for Config {
-    fn default() -> Self {
-        Config { categories: HashMap::new() }
-    }
+#[derive(Parser)]
+#[clap(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Opts {
+    #[clap(subcommand)]
+    command: Option<Command>,
+    #[clap(flatten)]
+    search: SearchOpts,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-struct CategoryConfig {
-    dirs: Vec<String>,
+#[derive(Subcommand)]
+enum Command {
+    #[clap(about = "Build the filename index of the categories, so that searches don't need to walk their dirs")]
+    Index {
+        #[clap(required = true)]
+        categories: Vec<String>,
+        #[clap(long, value_name = "N", help = "Walk the category dirs with N threads, one per CPU by default")]
+        threads: Option<usize>,
+    },
+    #[clap(about = "Keep the indexes of the categories (all of them by default) up to date with filesystem events")]
+    Watch {
+        categories: Vec<String>,
+    },
+    #[clap(about = "Keep the listings of all categories in memory and answer searches over a Unix socket")]
+    Daemon,
+    #[clap(about = "List the numbers missing between the lowest and highest ones following the prefix in the file names")]
+    Gaps {
+        category: String,
+        prefix: String,
+        #[clap(long, help = "Walk the category dirs even if the category is indexed or a daemon is running")]
+        live: bool,
+    },
+    #[clap(about = "List how the file names starting with the partial term continue, with the number of files for each")]
+    Complete {
+        category: String,
+        term: String,
+        #[clap(long, value_name = "N", help = "Group the names by their next N chars instead of their next token")]
+        chars: Option<usize>,
+        #[clap(long, help = "Only print the continuations, for shell completion")]
+        plain: bool,
+        #[clap(long, help = "Walk the category dirs even if the category is indexed or a daemon is running")]
+        live: bool,
+    },
+    #[clap(about = "Show the most common file name prefixes of the category, and the number and size of files per dir")]
+    Stats {
+        category: String,
+        #[clap(long, value_name = "N", default_value_t = 20, help = "How many prefixes to show")]
+        top: usize,
+        #[clap(long, value_name = "N", help = "Walk the category dirs with N threads, one per CPU by default")]
+        threads: Option<usize>,
+    },
 }
 
-#[derive(Parser)]
-struct Opts {
-    search_category: String,
+#[derive(Args)]
+struct SearchOpts {
     #[clap(required = true)]
+    search_category: Option<String>,
+    #[clap(required = true, help = "Name terms (optionally prefix:, contains:, suffix: or exact:) of which any has to match, and filters (ext:mp4,mkv, size>1G, mtime<30d, type:dir) which all have to hold, ! negates either. Ranges like ABC-100..120 and alternatives like ABC-1{0,1}5 expand into one term each")]
     search_terms: Vec<String>,
     #[clap(short, long, help = "To use the command in shell's if-else condition")]
     question: bool,
+    #[clap(short = 'x', long, value_name = "TERM", help = "Skip the files matching the term, same as a !TERM search term")]
+    exclude: Vec<String>,
+    #[clap(long, help = "Walk the category dirs even if the category is indexed or a daemon is running")]
+    live: bool,
+    #[clap(long, value_name = "prefix|contains|suffix|exact|fuzzy", help = "How the terms match the file names: suffix and exact compare with the name without its extension, fuzzy matches are ranked best first")]
+    mode: Option<Mode>,
+    #[clap(long, conflicts_with = "regex", help = "The terms are globs with *, ? and [...] wildcards")]
+    glob: bool,
+    #[clap(long, help = "The terms are regexes, matched anywhere in the name unless anchored")]
+    regex: bool,
+    #[clap(short, long, help = "Match regardless of letter case")]
+    ignore_case: bool,
+    #[clap(long, value_name = "none|nfc|nfkc", help = "Unicode normalization applied to both file names and terms before matching")]
+    normalize: Option<Normalization>,
+    #[clap(short, long, help = "Match full-width and half-width forms, and hiragana and katakana, as the same characters")]
+    japanese: bool,
+    #[clap(short, long, help = "Only match if the term is followed by a non-alphanumeric character or the end of the name")]
+    word_boundary: bool,
+    #[clap(long, help = "Match IDs regardless of separators, zero padding and letter case, e.g. ABC-123 finds ABC_00123 and abc123")]
+    id: bool,
+    #[clap(long, value_name = "REGEX", help = "Compare the terms with the part of the file names matched by the regex (its first group if it has one)")]
+    extract: Option<String>,
+    #[clap(long, value_name = "RULE", help = "Remove leading noise from the file names before matching: brackets, date, track or a regex")]
+    strip: Vec<String>,
+    #[clap(long, value_name = "EXT", value_delimiter = ',', help = "Only count files with these extensions, e.g. mp4,mkv, instead of the ones of the category")]
+    ext: Vec<String>,
+    #[clap(long, value_name = "SIZE", help = "Only count files at least this large, e.g. 100M or 1.5G")]
+    min_size: Option<String>,
+    #[clap(long, value_name = "SIZE", help = "Only count files at most this large")]
+    max_size: Option<String>,
+    #[clap(long, value_name = "AGE", help = "Only count files modified less than this long ago, e.g. 30d or 12h")]
+    newer_than: Option<String>,
+    #[clap(long, value_name = "AGE", help = "Only count files modified more than this long ago")]
+    older_than: Option<String>,
+    #[clap(long = "type", value_name = "file|dir", help = "Only count files or only dirs")]
+    file_type: Option<FileType>,
+    #[clap(long, value_name = "N", help = "Walk at most N levels deep, 1 being the entries of the category dirs")]
+    max_depth: Option<usize>,
+    #[clap(long, help = "Walk into symlinked dirs")]
+    follow_symlinks: bool,
+    #[clap(long, conflicts_with = "no_hidden", help = "Walk files and dirs whose names start with a dot")]
+    hidden: bool,
+    #[clap(long, help = "Skip files and dirs whose names start with a dot")]
+    no_hidden: bool,
+    #[clap(long, help = "Don't walk into dirs on other filesystems")]
+    one_file_system: bool,
+    #[clap(long, value_name = "N", help = "Walk the category dirs with N threads, one per CPU by default")]
+    threads: Option<usize>,
 }
 
 fn main() -> Result<()> {
@@ -47,21 +123,113 @@ fn main() -> Result<()> {
 
     let opts = match Opts::try_parse() {
         Ok(opts) => opts,
+        Err(e) if e.kind() == ErrorKind::DisplayHelp => e.exit(),
         Err(_) => {
             let categories = config.categories.keys().cloned().collect::<Vec<_>>().join(", ");
-            eprintln!("Usage: prefix-search [{categories}] [-q] <SEARCH_TERM> [<SEARCH_TERM>...]");
+            eprintln!("Usage: prefix-search [{categories}] [OPTIONS] <SEARCH_TERM> [<SEARCH_TERM>...]");
+            eprintln!("       prefix-search index <CATEGORY>...");
+            eprintln!("       prefix-search watch [<CATEGORY>...]");
+            eprintln!("       prefix-search daemon");
+            eprintln!("       prefix-search gaps <CATEGORY> <PREFIX>");
+            eprintln!("       prefix-search complete <CATEGORY> <TERM>");
+            eprintln!("       prefix-search stats <CATEGORY>");
+            eprintln!("See prefix-search --help for the options");
             exit(1);
         }
     };
+
+    match opts.command {
+        Some(Command::Index { categories, threads }) => index(&config, categories, threads),
+        Some(Command::Watch { categories }) => watch(&config, categories),
+        Some(Command::Daemon) => Ok(Daemon::new(config)?.run()?),
+        Some(Command::Gaps { category, prefix, live }) => gaps(&config, category, prefix, live),
+        Some(Command::Complete { category, term, chars, plain, live }) => complete(&config, category, term, chars, plain, live),
+        Some(Command::Stats { category, top, threads }) => stats(&config, category, top, threads),
+        None => search(&config, opts.search),
+    }
+}
+
+fn index(config: &Config, categories: Vec<String>, threads: Option<usize>) -> Result<()> {
+    for name in categories {
+        let mut category = config.category(&name)?.clone();
+        category.walk.threads = threads.or(category.walk.threads);
+        let index = Index::build(&category)?;
+        index.save(&name)?;
+        println!("Indexed {} files of {}", index.len(), name);
+    }
+    Ok(())
+}
+
+fn watch(config: &Config, categories: Vec<String>) -> Result<()> {
+    let names = if categories.is_empty() { config.categories.keys().cloned().collect() } else { categories };
+    let categories = names.iter().map(|name| Ok((name.as_str(), config.category(name)?))).collect::<Result<Vec<_>>>()?;
+
+    // subscribe first, so that nothing changed while loading the indexes is missed
+    let watcher = Watcher::new(categories.iter().copied())?;
+    let mut indexes = HashMap::new();
+    for (name, category) in &categories {
+        indexes.insert(name.to_string(), Index::load_or_build(name, category)?);
+    }
+    log::info!("Watching {}", names.join(", "));
+
+    loop {
+        for name in apply_changes(watcher.recv()?, &mut indexes, config)? {
+            indexes[&name].save(&name)?;
+            log::info!("Updated index of {}", name);
+        }
+    }
+}
+
+fn search(config: &Config, opts: SearchOpts) -> Result<()> {
     let quiet = opts.question;
     let use_failed_exit_code_if_no_match = opts.question;
     let only_first_match = opts.question;
 
-    let category = config.categories.get(&opts.search_category).ok_or(Error::CategoryNotFound(opts.search_category))?;
-    let mut seen_terms = HashSet::new();
+    let category_name = opts.search_category.unwrap_or_default();
+    let mut category = config.category(&category_name)?.clone();
+    let mut walk = category.walk.clone();
+    walk.max_depth = opts.max_depth.or(walk.max_depth);
+    walk.follow_symlinks |= opts.follow_symlinks;
+    if opts.hidden {
+        walk.include_hidden = true;
+    } else if opts.no_hidden {
+        walk.include_hidden = false;
+    }
+    walk.one_file_system |= opts.one_file_system;
+    walk.threads = opts.threads.or(walk.threads);
+    // the index and the daemon only know the files walked as the category says
+    let live = opts.live || !walk.walks_same(&category.walk);
+    category.walk = walk;
+    let mut options = category.options.clone();
+    options.mode = opts.mode.unwrap_or(options.mode);
+    if opts.glob {
+        options.syntax = Syntax::Glob;
+    } else if opts.regex {
+        options.syntax = Syntax::Regex;
+    }
+    options.ignore_case |= opts.ignore_case;
+    options.normalize = opts.normalize.unwrap_or(options.normalize);
+    options.japanese |= opts.japanese;
+    options.word_boundary |= opts.word_boundary;
+    if opts.id && options.id.is_none() {
+        options.id = Some(IdNormalization::default());
+    }
+    options.extract = opts.extract.or(options.extract);
+    options.strip.extend(opts.strip);
+    if !opts.ext.is_empty() {
+        options.extensions = opts.ext;
+    }
+    options.min_size = opts.min_size.or(options.min_size);
+    options.max_size = opts.max_size.or(options.max_size);
+    options.newer_than = opts.newer_than.or(options.newer_than);
+    options.older_than = opts.older_than.or(options.older_than);
+    options.file_type = opts.file_type.or(options.file_type);
     let mut terms = opts.search_terms;
-    // longest term first
-    terms.sort_by(|a, b| b.len().cmp(&a.len()));
+    terms.extend(opts.exclude.into_iter().map(|term| format!("!{term}")));
+    let request = Request { category: category_name, terms, options, dirs: category.dirs.clone(), walk: category.walk.clone() };
+    let searcher = Searcher::with_options(&category, request.options.clone(), request.terms.clone())?;
+    let mut seen_terms = HashSet::new();
+    let mut n_excluded = HashMap::<String, usize>::new();
 
     let mut stdout = StandardStream::stdout(ColorChoice::Always);
 
@@ -75,45 +243,50 @@ fn main() -> Result<()> {
 
     let mut n_found = 0;
 
-    for dir in &category.dirs {
-        log::debug!("Searching in dir: {}", dir);
-        let paths = jdt::walk_dir(dir, |path| path);
-        log::debug!("Found {} paths", paths.len());
-        for path in paths {
-            let filename = path.file_name().ok_or(Error::CouldntGetFileName(path.clone()))?;
-            let filename = filename.to_string_lossy();
-            for term in &terms {
-                if filename.starts_with(&*term) {
-                    let matched_str = &filename[0..term.len()];
-                    let unmatched_str = &filename[term.len()..];
-
-                    if !quiet {
-                        stdout.set_color(&matched_color)?;
-                        write!(&mut stdout, "{}", matched_str)?;
-                        stdout.set_color(&unmatched_color)?;
-                        write!(&mut stdout, "{}", unmatched_str)?;
-                        stdout.set_color(&path_color)?;
-                        write!(&mut stdout, " ({})", path.display())?;
-                        stdout.reset()?;
-                        writeln!(&mut stdout)?;
-                    }
-
-                    n_found += 1;
-                    seen_terms.insert(term.clone());
-                    break;
-                }
+    let on_match = |m: Match| -> Result<_> {
+        if let Some(exclusion) = m.excluded_by {
+            *n_excluded.entry(exclusion).or_default() += 1;
+            return Ok(ControlFlow::Continue(()));
+        }
+        if !quiet {
+            let filename = m.file_name()?;
+            let mut end = 0;
+            for range in &m.matched {
+                stdout.set_color(&unmatched_color)?;
+                write!(&mut stdout, "{}", &filename[end..range.start])?;
+                stdout.set_color(&matched_color)?;
+                write!(&mut stdout, "{}", &filename[range.clone()])?;
+                end = range.end;
             }
-            if only_first_match && n_found > 0 {
-                break;
+            stdout.set_color(&unmatched_color)?;
+            write!(&mut stdout, "{}", &filename[end..])?;
+            stdout.set_color(&path_color)?;
+            if let Some(normalized) = &m.normalized {
+                write!(&mut stdout, " [{}]", normalized)?;
             }
+            write!(&mut stdout, " ({})", m.path.display())?;
+            stdout.reset()?;
+            writeln!(&mut stdout)?;
         }
-    }
-    let unseen_terms = terms.into_iter().filter(|term| !seen_terms.contains(term));
-    let unseen_terms = unseen_terms.collect::<HashSet<_>>();
+
+        n_found += 1;
+        seen_terms.insert(m.term);
+        if only_first_match {
+            Ok(ControlFlow::Break(()))
+        } else {
+            Ok(ControlFlow::Continue(()))
+        }
+    };
+    run_search(config, &request, &searcher, live, on_match)?;
+
+    let unseen_terms = searcher.terms().filter(|term| !seen_terms.contains(*term)).collect::<Vec<_>>();
     if !quiet {
         println!("Found {} files", n_found);
+        for exclusion in searcher.exclusions() {
+            println!("Excluded {} files matching {}", n_excluded.get(exclusion).unwrap_or(&0), exclusion);
+        }
         if !unseen_terms.is_empty() {
-            println!("Unmet search terms: {}", unseen_terms.iter().cloned().collect::<Vec<_>>().join(" "));
+            println!("Unmet search terms: {}", unseen_terms.join(" "));
         }
     }
 
@@ -128,3 +301,120 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+fn gaps(config: &Config, category_name: String, prefix: String, live: bool) -> Result<()> {
+    let (request, searcher) = prefix_search(config, category_name, &prefix)?;
+    let mut series = Series::new(&prefix);
+    run_search(config, &request, &searcher, live, |m| {
+        series.insert(&m)?;
+        Ok(ControlFlow::Continue(()))
+    })?;
+
+    let (Some(first), Some(last)) = (series.first(), series.last()) else {
+        println!("No numbered files start with {}", prefix);
+        return Ok(());
+    };
+    let gaps = series.gaps();
+    for gap in &gaps {
+        println!("{}", series.range_name(gap));
+    }
+    let n_missing = gaps.iter().map(|gap| gap.end() - gap.start() + 1).sum::<u64>();
+    println!("Found {} numbers from {} to {}, {} missing", series.numbers.len(), series.name(first), series.name(last), n_missing);
+    Ok(())
+}
+
+fn complete(config: &Config, category_name: String, term: String, chars: Option<usize>, plain: bool, live: bool) -> Result<()> {
+    let (request, searcher) = prefix_search(config, category_name, &term)?;
+    let mut completions = Completions::new(chars);
+    run_search(config, &request, &searcher, live, |m| {
+        completions.insert(&m)?;
+        Ok(ControlFlow::Continue(()))
+    })?;
+
+    for (continuation, n_files) in &completions.groups {
+        if plain {
+            println!("{}", continuation);
+        } else {
+            println!("{} ({})", completions.label(continuation), count_files(*n_files));
+        }
+    }
+    Ok(())
+}
+
+fn stats(config: &Config, category_name: String, top: usize, threads: Option<usize>) -> Result<()> {
+    let mut category = config.category(&category_name)?.clone();
+    category.walk.threads = threads.or(category.walk.threads);
+    let stats = Stats::collect(&category, top)?;
+
+    let width = stats.prefixes.iter().map(|(prefix, _)| prefix.chars().count()).max().unwrap_or(0).max("Prefix".len());
+    println!("{:width$}  Files", "Prefix");
+    for (prefix, n_files) in &stats.prefixes {
+        println!("{:width$}  {}", prefix, n_files);
+    }
+    println!();
+    for dir in &stats.dirs {
+        println!("{}: {} files, {}", dir.dir, dir.n_files, format_size(dir.size));
+    }
+    let (n_files, size) = stats.dirs.iter().fold((0, 0), |(n_files, size), dir| (n_files + dir.n_files, size + dir.size));
+    println!("Total: {} files, {}", n_files, format_size(size));
+    Ok(())
+}
+
+fn count_files(n_files: usize) -> String {
+    if n_files == 1 {
+        "1 file".to_string()
+    } else {
+        format!("{} files", n_files)
+    }
+}
+
+fn format_size(size: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if size < 1024 {
+        return format!("{} B", size);
+    }
+    let mut size = size as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+// a search for the names starting with the term, whatever the match mode and syntax of the category
+fn prefix_search<'a>(config: &'a Config, category_name: String, term: &str) -> Result<(Request, Searcher<'a>)> {
+    let category = config.category(&category_name)?;
+    let mut options = category.options.clone();
+    options.mode = Mode::Prefix;
+    options.syntax = Syntax::Literal;
+    // what follows the term is usually a number, which is no word boundary
+    options.word_boundary = false;
+    let request = Request { category: category_name, terms: vec![term.to_string()], options, dirs: category.dirs.clone(), walk: category.walk.clone() };
+    let searcher = Searcher::with_options(category, request.options.clone(), request.terms.clone())?;
+    Ok((request, searcher))
+}
+
+// asks the daemon if it runs and knows the category as configured, otherwise uses the index of the category if it has
+// one, otherwise walks its dirs
+fn run_search<F>(config: &Config, request: &Request, searcher: &Searcher, live: bool, mut on_match: F) -> Result<()>
+where
+    F: FnMut(Match) -> Result<ControlFlow<()>>,
+{
+    if live {
+        return searcher.search(on_match);
+    }
+    match Client::connect() {
+        Ok(Some(client)) => {
+            if client.search(request, &mut on_match)? {
+                return Ok(());
+            }
+            log::warn!("The daemon doesn't know {} as configured, searching without it", request.category);
+        }
+        Ok(None) => {}
+        Err(e) => log::warn!("Could not connect to the daemon, searching without it: {}", e),
+    }
+    match Index::load(&request.category, config.category(&request.category)?)? {
+        Some(index) => searcher.search_index(&index, on_match),
+        None => searcher.search(on_match),
+    }
+}
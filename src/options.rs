@@ -0,0 +1,220 @@
+use std::{fmt, str::FromStr};
+use serde::{Deserialize, Serialize};
+use crate::Error;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Normalization {
+    #[default]
+    None,
+    Nfc,
+    Nfkc,
+}
+
+impl FromStr for Normalization {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Normalization::None),
+            "nfc" => Ok(Normalization::Nfc),
+            "nfkc" => Ok(Normalization::Nfkc),
+            _ => Err(Error::UnknownNormalization(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Normalization {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Normalization::None => write!(f, "none"),
+            Normalization::Nfc => write!(f, "nfc"),
+            Normalization::Nfkc => write!(f, "nfkc"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    #[default]
+    Prefix,
+    Contains,
+    /// The stem, the name without its extension, ends with the term.
+    Suffix,
+    /// The stem is the term.
+    Exact,
+    /// The chars of the term appear in order anywhere in the name, matches are ranked by how well they match.
+    Fuzzy,
+}
+
+impl Mode {
+    /// Whether terms are compared with the stem instead of the whole name.
+    pub fn compares_stem(self) -> bool {
+        matches!(self, Mode::Suffix | Mode::Exact)
+    }
+}
+
+impl FromStr for Mode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "prefix" => Ok(Mode::Prefix),
+            "contains" => Ok(Mode::Contains),
+            "suffix" => Ok(Mode::Suffix),
+            "exact" => Ok(Mode::Exact),
+            "fuzzy" => Ok(Mode::Fuzzy),
+            _ => Err(Error::UnknownMode(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mode::Prefix => write!(f, "prefix"),
+            Mode::Contains => write!(f, "contains"),
+            Mode::Suffix => write!(f, "suffix"),
+            Mode::Exact => write!(f, "exact"),
+            Mode::Fuzzy => write!(f, "fuzzy"),
+        }
+    }
+}
+
+/// How the search terms are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Syntax {
+    #[default]
+    Literal,
+    /// `*`, `?` and `[...]` wildcards, anchored like literal terms in the match mode.
+    Glob,
+    /// Regexes, matched anywhere in the name (or the stem with suffix and exact modes) unless anchored.
+    Regex,
+}
+
+impl FromStr for Syntax {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "literal" => Ok(Syntax::Literal),
+            "glob" => Ok(Syntax::Glob),
+            "regex" => Ok(Syntax::Regex),
+            _ => Err(Error::UnknownSyntax(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Syntax {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Syntax::Literal => write!(f, "literal"),
+            Syntax::Glob => write!(f, "glob"),
+            Syntax::Regex => write!(f, "regex"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileType {
+    File,
+    Dir,
+}
+
+impl FromStr for FileType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "file" => Ok(FileType::File),
+            "dir" => Ok(FileType::Dir),
+            _ => Err(Error::UnknownFileType(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for FileType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FileType::File => write!(f, "file"),
+            FileType::Dir => write!(f, "dir"),
+        }
+    }
+}
+
+/// How file names and search terms are compared and which files count, settable per category and per search.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MatchOptions {
+    #[serde(default)]
+    pub mode: Mode,
+    /// Glob and regex terms are matched against the names as they are, only `ignore_case` applies to them.
+    #[serde(default)]
+    pub syntax: Syntax,
+    #[serde(default)]
+    pub ignore_case: bool,
+    #[serde(default)]
+    pub normalize: Normalization,
+    /// Treat full-width and half-width forms, and hiragana and katakana, as the same characters.
+    #[serde(default)]
+    pub japanese: bool,
+    /// Only count a match if it's followed by a separator or the end of the name.
+    #[serde(default)]
+    pub word_boundary: bool,
+    /// Compare IDs regardless of separators, zero padding and letter case, so that `ABC-123` also finds `ABC_00123` and
+    /// `abc123`.
+    #[serde(default)]
+    pub id: Option<IdNormalization>,
+    /// Rules removing leading noise from the file names, see `StripRules`.
+    #[serde(default)]
+    pub strip: Vec<String>,
+    /// Regex picking the key compared with the terms out of the file name, the first group if it has one.
+    #[serde(default)]
+    pub extract: Option<String>,
+    /// Only files with one of these extensions count, any file if empty.
+    #[serde(default)]
+    pub extensions: Vec<String>,
+    /// Sizes like `100M` or `1.5G`, see `parse_size`.
+    #[serde(default)]
+    pub min_size: Option<String>,
+    #[serde(default)]
+    pub max_size: Option<String>,
+    /// Times since the last modification like `30d` or `12h`, see `parse_duration`.
+    #[serde(default)]
+    pub newer_than: Option<String>,
+    #[serde(default)]
+    pub older_than: Option<String>,
+    #[serde(default, rename = "type")]
+    pub file_type: Option<FileType>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct IdNormalization {
+    #[serde(default = "IdNormalization::default_separators")]
+    pub separators: String,
+}
+
+impl Default for IdNormalization {
+    fn default() -> Self {
+        IdNormalization { separators: Self::default_separators() }
+    }
+}
+
+impl IdNormalization {
+    fn default_separators() -> String {
+        "-_ .".to_string()
+    }
+}
+
+impl MatchOptions {
+    /// Whether names are compared as they are, byte by byte.
+    pub fn is_verbatim(&self) -> bool {
+        !self.ignore_case && self.normalize == Normalization::None && !self.japanese && self.id.is_none()
+    }
+
+    pub(crate) fn composes(&self) -> bool {
+        self.normalize != Normalization::None || self.japanese
+    }
+}
@@ -0,0 +1,117 @@
+use regex::{Regex, RegexBuilder};
+use crate::{MatchOptions, Mode, Result, Syntax};
+
+/// Compiles a glob or regex term matched in the mode, `None` for literal terms.
+pub fn compile(term: &str, mode: Mode, options: &MatchOptions) -> Result<Option<Regex>> {
+    let pattern = match options.syntax {
+        Syntax::Literal => return Ok(None),
+        Syntax::Glob => {
+            let glob = glob_to_regex(term);
+            match mode {
+                Mode::Prefix => format!(r"\A(?:{glob})"),
+                Mode::Contains | Mode::Fuzzy => glob,
+                Mode::Suffix => format!(r"(?:{glob})\z"),
+                Mode::Exact => format!(r"\A(?:{glob})\z"),
+            }
+        }
+        Syntax::Regex => term.to_string(),
+    };
+    Ok(Some(RegexBuilder::new(&pattern).case_insensitive(options.ignore_case).build()?))
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    translate_glob(glob, false)
+}
+
+/// Translates a glob matched against `/` separated paths: `*` and `?` don't match `/`, and `**` matches any number of
+/// dirs, as in gitignore files.
+pub fn path_glob_to_regex(glob: &str) -> String {
+    translate_glob(glob, true)
+}
+
+fn translate_glob(glob: &str, is_path: bool) -> String {
+    let (any_chars, any_char) = if is_path { ("[^/]*", "[^/]") } else { (".*", ".") };
+    let mut regex = String::with_capacity(glob.len() * 2);
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if is_path && chars.peek() == Some(&'*') => {
+                chars.next();
+                // **/ also matches no dir at all
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    regex.push_str("(?:.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str(any_chars),
+            '?' => regex.push_str(any_char),
+            // an unclosed [ is just a [
+            '[' if !chars.clone().any(|c| c == ']') => regex.push_str(r"\["),
+            '[' => {
+                let class = chars.by_ref().take_while(|&c| c != ']').collect::<String>();
+                let (negated, class) = match class.strip_prefix('!') {
+                    Some(class) => (true, class),
+                    None => (false, class.as_str()),
+                };
+                regex.push('[');
+                if negated {
+                    // a class never matches the separator of a path
+                    regex.push_str(if is_path { "^/" } else { "^" });
+                }
+                for c in class.chars() {
+                    // keep ranges like a-z, escape everything else that means something in a regex class
+                    if c != '-' {
+                        regex.push_str(&regex::escape(&c.to_string()));
+                    } else {
+                        regex.push('-');
+                    }
+                }
+                regex.push(']');
+            }
+            '\\' => regex.push_str(&regex::escape(&chars.next().map_or(String::new(), |c| c.to_string()))),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_match(glob: &str, s: &str) -> bool {
+        Regex::new(&format!("^{}$", path_glob_to_regex(glob))).unwrap().is_match(s)
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(glob_to_regex("a*b?.mp4"), r"a.*b.\.mp4");
+        assert_eq!(glob_to_regex("[!0-9]"), "[^0-9]");
+        assert_eq!(glob_to_regex("ABC[1"), r"ABC\[1");
+        assert_eq!(glob_to_regex("[ab]c["), r"[ab]c\[");
+        assert_eq!(glob_to_regex(r"\*"), r"\*");
+    }
+
+    #[test]
+    fn paths() {
+        assert!(is_match("*.nfo", "a.nfo"));
+        assert!(!is_match("*.nfo", "a/b.nfo"));
+        assert!(!is_match("a?b", "a/b"));
+        assert!(is_match("a/**/b", "a/b"));
+        assert!(is_match("a/**/b", "a/x/y/b"));
+        assert!(is_match("a/**", "a/x/y"));
+        assert!(!is_match("a/**/b", "ab"));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(is_match("[ab]-[0-9]", "a-5"));
+        assert!(!is_match("[ab]-[0-9]", "c-5"));
+        assert!(is_match("x[!0-9]", "xa"));
+        assert!(!is_match("x[!0-9]", "x1"));
+        assert!(!is_match("x[!0-9]y", "x/y"));
+        assert!(is_match("[.]nfo", ".nfo"));
+    }
+}
@@ -0,0 +1,200 @@
+use std::{fs::Metadata, path::Path, time::{Duration, SystemTime}};
+use crate::{Error, FileType, Mode, Result};
+
+/// A search term, either compared with the file names or checked against the file metadata.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Predicate {
+    /// `prefix:ABC`, `contains:`, `suffix:` and `exact:` terms use that mode, bare terms the mode of the search.
+    Name(Option<Mode>, String),
+    Filter(Filter),
+}
+
+/// A condition on a file other than its name.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    /// `ext:mp4,mkv`, compared regardless of case.
+    Ext(Vec<String>),
+    /// `size>1G`, with K, M, G and T being powers of 1024.
+    Size(Comparison, u64),
+    /// `mtime<30d`, the time since the last modification, in s, m, h, d, w or y.
+    Age(Comparison, Duration),
+    /// `type:file` or `type:dir`.
+    Type(FileType),
+    Not(Box<Filter>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl Comparison {
+    fn holds<T: PartialOrd>(self, left: T, right: T) -> bool {
+        match self {
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Equal => left == right,
+            Comparison::GreaterOrEqual => left >= right,
+            Comparison::Greater => left > right,
+        }
+    }
+}
+
+/// Parses a search term. Terms that don't start with a known key are name terms, so `ABC:1` is just a name.
+pub fn parse(term: &str) -> Result<Predicate> {
+    let key_len = term.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(term.len());
+    let (key, rest) = term.split_at(key_len);
+    let mode = match key {
+        "prefix" => Some(Mode::Prefix),
+        "contains" => Some(Mode::Contains),
+        "suffix" => Some(Mode::Suffix),
+        "exact" => Some(Mode::Exact),
+        _ => None,
+    };
+    if let (Some(mode), Some(value)) = (mode, rest.strip_prefix(':')) {
+        return Ok(Predicate::Name(Some(mode), value.to_string()));
+    }
+    let filter = match (key, rest.strip_prefix(':'), parse_comparison(rest)) {
+        ("ext", Some(value), _) => Filter::ext(value.split(',')),
+        ("type", Some(value), _) => Filter::Type(value.parse()?),
+        // without an operator, like `size-chart`, it's a name
+        ("size", _, Some((comparison, value))) => Filter::size(comparison, value)?,
+        ("mtime", _, Some((comparison, value))) => Filter::age(comparison, value)?,
+        _ => return Ok(Predicate::Name(None, term.to_string())),
+    };
+    Ok(Predicate::Filter(filter))
+}
+
+impl Filter {
+    /// Extensions are compared without their leading dot.
+    pub fn ext<S: AsRef<str>>(extensions: impl IntoIterator<Item = S>) -> Self {
+        Filter::Ext(extensions.into_iter().map(|ext| ext.as_ref().trim_start_matches('.').to_lowercase()).collect())
+    }
+
+    /// Parses the size with `parse_size`.
+    pub fn size(comparison: Comparison, size: &str) -> Result<Self> {
+        Ok(Filter::Size(comparison, parse_size(size).ok_or_else(|| invalid(size, "expected a size like 100M or 1.5G"))?))
+    }
+
+    /// Parses the age with `parse_duration`.
+    pub fn age(comparison: Comparison, age: &str) -> Result<Self> {
+        Ok(Filter::Age(comparison, parse_duration(age).ok_or_else(|| invalid(age, "expected a duration like 30d or 12h"))?))
+    }
+
+    pub fn matches(&self, path: &Path, metadata: &Metadata) -> bool {
+        match self {
+            Filter::Ext(extensions) => path.extension().is_some_and(|ext| extensions.contains(&ext.to_string_lossy().to_lowercase())),
+            Filter::Size(comparison, size) => comparison.holds(metadata.len(), *size),
+            Filter::Age(comparison, age) => {
+                let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                // modified in the future counts as just now
+                comparison.holds(modified.elapsed().unwrap_or_default(), *age)
+            }
+            Filter::Type(FileType::File) => metadata.is_file(),
+            Filter::Type(FileType::Dir) => metadata.is_dir(),
+            Filter::Not(filter) => !filter.matches(path, metadata),
+        }
+    }
+}
+
+fn parse_comparison(s: &str) -> Option<(Comparison, &str)> {
+    // the longer operators first
+    let operators = [("<=", Comparison::LessOrEqual), (">=", Comparison::GreaterOrEqual), ("<", Comparison::Less), (">", Comparison::Greater), ("=", Comparison::Equal)];
+    operators.iter().find_map(|(operator, comparison)| s.strip_prefix(operator).map(|value| (*comparison, value)))
+}
+
+/// Parses sizes like `100`, `100M`, `1.5G` or `2TiB`, the units being powers of 1024.
+pub fn parse_size(s: &str) -> Option<u64> {
+    let (number, unit) = split_number(s);
+    let exponent = match unit.to_ascii_lowercase().trim_end_matches("ib").trim_end_matches('b') {
+        "" => 0,
+        "k" => 1,
+        "m" => 2,
+        "g" => 3,
+        "t" => 4,
+        _ => return None,
+    };
+    Some((number.parse::<f64>().ok()? * 1024f64.powi(exponent)) as u64)
+}
+
+/// Parses durations like `90s`, `30m`, `12h`, `30d`, `2w` or `1y`.
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    let (number, unit) = split_number(s);
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        "y" => 365 * 24 * 60 * 60,
+        _ => return None,
+    };
+    Duration::try_from_secs_f64(number.parse::<f64>().ok()? * seconds as f64).ok()
+}
+
+fn split_number(s: &str) -> (&str, &str) {
+    s.split_at(s.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len()))
+}
+
+fn invalid(term: &str, reason: &str) -> Error {
+    Error::InvalidFilter(term.to_string(), reason.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(term: &str) -> Predicate {
+        Predicate::Name(None, term.to_string())
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(parse("ABC-123").unwrap(), name("ABC-123"));
+        assert_eq!(parse("ABC:1").unwrap(), name("ABC:1"));
+        assert_eq!(parse("size-chart").unwrap(), name("size-chart"));
+        assert_eq!(parse("sizes").unwrap(), name("sizes"));
+        assert_eq!(parse("size").unwrap(), name("size"));
+        assert_eq!(parse("mtime_log").unwrap(), name("mtime_log"));
+        assert_eq!(parse("exten:sion").unwrap(), name("exten:sion"));
+        assert_eq!(parse("ext").unwrap(), name("ext"));
+    }
+
+    #[test]
+    fn modes() {
+        assert_eq!(parse("prefix:ABC").unwrap(), Predicate::Name(Some(Mode::Prefix), "ABC".to_string()));
+        assert_eq!(parse("exact:size>1").unwrap(), Predicate::Name(Some(Mode::Exact), "size>1".to_string()));
+        assert_eq!(parse("contains:").unwrap(), Predicate::Name(Some(Mode::Contains), String::new()));
+    }
+
+    #[test]
+    fn filters() {
+        assert_eq!(parse("ext:.MP4,mkv").unwrap(), Predicate::Filter(Filter::Ext(vec!["mp4".to_string(), "mkv".to_string()])));
+        assert_eq!(parse("type:dir").unwrap(), Predicate::Filter(Filter::Type(FileType::Dir)));
+        assert_eq!(parse("size>=1.5K").unwrap(), Predicate::Filter(Filter::Size(Comparison::GreaterOrEqual, 1536)));
+        assert_eq!(parse("size<1").unwrap(), Predicate::Filter(Filter::Size(Comparison::Less, 1)));
+        assert_eq!(parse("mtime>2d").unwrap(), Predicate::Filter(Filter::Age(Comparison::Greater, Duration::from_secs(2 * 24 * 60 * 60))));
+        assert_eq!(parse("mtime=90s").unwrap(), Predicate::Filter(Filter::Age(Comparison::Equal, Duration::from_secs(90))));
+    }
+
+    #[test]
+    fn invalid_filters() {
+        assert!(matches!(parse("size>big"), Err(Error::InvalidFilter(..))));
+        assert!(matches!(parse("mtime<30"), Err(Error::InvalidFilter(..))));
+        assert!(parse("type:link").is_err());
+    }
+
+    #[test]
+    fn sizes_and_durations() {
+        assert_eq!(parse_size("100"), Some(100));
+        assert_eq!(parse_size("2TiB"), Some(2 << 40));
+        assert_eq!(parse_size("1mb"), Some(1 << 20));
+        assert_eq!(parse_size("1X"), None);
+        assert_eq!(parse_duration("1w"), Some(Duration::from_secs(7 * 24 * 60 * 60)));
+        assert_eq!(parse_duration("1"), None);
+    }
+}
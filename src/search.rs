@@ -0,0 +1,349 @@
+use std::{cell::OnceCell, cmp::Reverse, collections::HashSet, fs, ops::{ControlFlow, Range}, path::{Path, PathBuf}};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use crate::{expand::expand, file_name, fuzzy::fuzzy_match, pattern, query, CategoryConfig, Comparison, Error, Filter, Folded, Index, MatchOptions, Mode, Predicate, Result, StripRules, Syntax, Walker};
+
+/// A file whose name (or key extracted from it) matched one of the search terms.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Match {
+    pub path: PathBuf,
+    pub term: String,
+    /// Byte ranges of the file name matched by the term, in order.
+    pub matched: Vec<Range<usize>>,
+    /// The normalized form both the term and the file name matched as, with ID normalization.
+    pub normalized: Option<String>,
+    /// How well the term matched in fuzzy mode, higher is better.
+    pub score: Option<u32>,
+    /// The exclusion term that also matched the name. Such matches are still passed to search callbacks, so that
+    /// they can be counted, but aren't found.
+    pub excluded_by: Option<String>,
+}
+
+impl Match {
+    pub fn file_name(&self) -> Result<String> {
+        file_name(&self.path)
+    }
+}
+
+struct Term {
+    // as given, e.g. prefix:ABC
+    text: String,
+    mode: Mode,
+    // what is compared with the names, e.g. ABC
+    folded: String,
+    pattern: Option<Regex>,
+}
+
+impl Term {
+    fn new(text: String, value: &str, mode: Mode, options: &MatchOptions) -> Result<Self> {
+        if options.syntax != Syntax::Literal && mode == Mode::Fuzzy {
+            return Err(Error::UnsupportedSyntax(options.syntax, mode));
+        }
+        let folded = Folded::new(value, options).text;
+        let pattern = pattern::compile(value, mode, options)?;
+        Ok(Term { text, mode, folded, pattern })
+    }
+}
+
+// the part of a file name compared with the terms, and the text it folds into
+struct Key<'k> {
+    key: &'k str,
+    folded: Option<Folded>,
+}
+
+impl<'k> Key<'k> {
+    fn new(key: &'k str, options: &MatchOptions) -> Self {
+        Key { key, folded: (!options.is_verbatim()).then(|| Folded::new(key, options)) }
+    }
+
+    fn text(&self) -> &str {
+        self.folded.as_ref().map_or(self.key, |folded| folded.text.as_str())
+    }
+
+    // maps a byte range of the text back onto the key
+    fn origin(&self, range: Range<usize>) -> Range<usize> {
+        self.folded.as_ref().map_or(range.clone(), |folded| folded.origin(range))
+    }
+}
+
+pub struct Searcher<'a> {
+    category: &'a CategoryConfig,
+    options: MatchOptions,
+    strip: StripRules,
+    extract: Option<Regex>,
+    // in the order they were given, after expansion
+    texts: Vec<String>,
+    // longest first
+    terms: Vec<Term>,
+    // in the order they were given
+    exclusions: Vec<Term>,
+    filters: Vec<Filter>,
+}
+
+impl<'a> Searcher<'a> {
+    pub fn new(category: &'a CategoryConfig, terms: Vec<String>) -> Result<Self> {
+        Self::with_options(category, category.options.clone(), terms)
+    }
+
+    /// Each term is parsed with `query::parse`: name terms match if any of them does, filters all have to hold.
+    /// Terms starting with `!` exclude the names they match, by prefix in fuzzy mode unless given a mode, or negate the
+    /// filter. Literal and glob terms are expanded first, see `expand`.
+    pub fn with_options(category: &'a CategoryConfig, options: MatchOptions, terms: Vec<String>) -> Result<Self> {
+        let expanded = expand_all(terms, &options)?;
+        let (mut texts, mut terms, mut exclusions, mut filters) = (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+        for text in expanded {
+            let (negated, text) = match text.strip_prefix('!') {
+                Some(text) => (true, text.to_string()),
+                None => (false, text),
+            };
+            match query::parse(&text)? {
+                Predicate::Name(mode, value) if negated => {
+                    // a fuzzy exclusion would leave out nearly everything, so bare ones exclude by prefix
+                    let default_mode = if options.mode == Mode::Fuzzy { Mode::Prefix } else { options.mode };
+                    exclusions.push(Term::new(text, &value, mode.unwrap_or(default_mode), &options)?);
+                }
+                Predicate::Name(mode, value) => {
+                    texts.push(text.clone());
+                    terms.push(Term::new(text, &value, mode.unwrap_or(options.mode), &options)?);
+                }
+                Predicate::Filter(filter) if negated => filters.push(Filter::Not(Box::new(filter))),
+                Predicate::Filter(filter) => filters.push(filter),
+            }
+        }
+        if !options.extensions.is_empty() {
+            filters.push(Filter::ext(&options.extensions));
+        }
+        if let Some(size) = &options.min_size {
+            filters.push(Filter::size(Comparison::GreaterOrEqual, size)?);
+        }
+        if let Some(size) = &options.max_size {
+            filters.push(Filter::size(Comparison::LessOrEqual, size)?);
+        }
+        if let Some(age) = &options.newer_than {
+            filters.push(Filter::age(Comparison::Less, age)?);
+        }
+        if let Some(age) = &options.older_than {
+            filters.push(Filter::age(Comparison::Greater, age)?);
+        }
+        if let Some(file_type) = options.file_type {
+            filters.push(Filter::Type(file_type));
+        }
+        // filters and exclusions alone apply to every name
+        if terms.is_empty() && !(filters.is_empty() && exclusions.is_empty()) {
+            terms.push(Term::new(String::new(), "", Mode::Prefix, &options)?);
+        }
+        terms.sort_by_key(|term| Reverse(term.folded.len()));
+        let strip = StripRules::new(&options.strip)?;
+        let extract = options.extract.as_deref().map(Regex::new).transpose()?;
+        Ok(Searcher { category, options, strip, extract, texts, terms, exclusions, filters })
+    }
+
+    /// The name terms after expansion, in the order they were given.
+    pub fn terms(&self) -> impl Iterator<Item = &str> {
+        self.texts.iter().map(String::as_str)
+    }
+
+    /// The exclusion terms after expansion, without their `!`.
+    pub fn exclusions(&self) -> impl Iterator<Item = &str> {
+        self.exclusions.iter().map(|term| term.text.as_str())
+    }
+
+    pub fn options(&self) -> &MatchOptions {
+        &self.options
+    }
+
+    pub fn match_path(&self, path: &Path) -> Result<Option<Match>> {
+        let filename = file_name(path)?;
+        let Some((offset, key)) = self.key(&filename) else { return Ok(None) };
+        let (whole, stemmed) = (OnceCell::new(), OnceCell::new());
+        let key_of = |term: &Term| match term.mode.compares_stem() {
+            true => stemmed.get_or_init(|| Key::new(stem(key, path), &self.options)),
+            false => whole.get_or_init(|| Key::new(key, &self.options)),
+        };
+
+        let mut best: Option<Match> = None;
+        for term in &self.terms {
+            let Some((matched, score)) = self.match_term(term, key_of(term)) else { continue };
+            let m = Match {
+                path: path.to_path_buf(),
+                term: term.text.clone(),
+                matched: matched.into_iter().map(|range| offset + range.start..offset + range.end).collect(),
+                normalized: self.options.id.as_ref().map(|_| term.folded.clone()),
+                score,
+                excluded_by: None,
+            };
+            // the longest matching term wins, unless a shorter one matches better
+            if best.as_ref().is_none_or(|best| m.score > best.score) {
+                best = Some(m);
+            }
+            if term.mode != Mode::Fuzzy {
+                break;
+            }
+        }
+        let Some(mut best) = best else { return Ok(None) };
+        if !self.filters.is_empty() {
+            let metadata = match fs::metadata(path) {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    log::warn!("Skipped {}: {}", path.display(), e);
+                    return Ok(None);
+                }
+            };
+            if !self.filters.iter().all(|filter| filter.matches(path, &metadata)) {
+                return Ok(None);
+            }
+        }
+        best.excluded_by = self.exclusions.iter().find(|term| self.match_term(term, key_of(term)).is_some()).map(|term| term.text.clone());
+        Ok(Some(best))
+    }
+
+    // the part of the file name compared with the terms and where it starts
+    fn key<'s>(&self, filename: &'s str) -> Option<(usize, &'s str)> {
+        let offset = self.strip.noise_len(filename);
+        let filename = &filename[offset..];
+        let Some(extract) = &self.extract else { return Some((offset, filename)) };
+        let captures = extract.captures(filename)?;
+        let key = captures.get(1).or_else(|| captures.get(0))?;
+        Some((offset + key.start(), key.as_str()))
+    }
+
+    // the byte ranges of the key matched by the term and how well they match
+    fn match_term(&self, term: &Term, key: &Key) -> Option<(Vec<Range<usize>>, Option<u32>)> {
+        let matched = match (term.mode, &term.pattern) {
+            (Mode::Fuzzy, _) => {
+                let (score, ranges) = fuzzy_match(key.text(), &term.folded)?;
+                return Some((merge_ranges(ranges.into_iter().map(|range| key.origin(range))), Some(score)));
+            }
+            (_, Some(pattern)) => pattern.find(key.key)?.range(),
+            (mode, None) => key.origin(find_literal(mode, key.text(), &term.folded)?),
+        };
+        if self.options.word_boundary && !is_boundary(key.key, matched.end) {
+            return None;
+        }
+        Some((vec![matched], None))
+    }
+
+    // whether the matches are the entries of the index whose names start with the terms
+    fn matches_name_prefixes(&self) -> bool {
+        self.terms.iter().all(|term| term.mode == Mode::Prefix)
+            && self.options.syntax == Syntax::Literal
+            && self.options.is_verbatim()
+            && self.options.strip.is_empty()
+            && self.extract.is_none()
+    }
+
+    /// Walks every dir of the category and calls `f` for each match until it returns `ControlFlow::Break`.
+    /// Fuzzy matches are passed best first once every file was compared.
+    pub fn search<F, E>(&self, f: F) -> std::result::Result<(), E>
+    where
+        F: FnMut(Match) -> std::result::Result<ControlFlow<()>, E>,
+        E: From<Error>,
+    {
+        self.visit(Walker::new(self.category)?.walk(), f)
+    }
+
+    /// Same as `search`, but looks the terms up in a prebuilt index instead of walking the dirs.
+    pub fn search_index<F, E>(&self, index: &Index, f: F) -> std::result::Result<(), E>
+    where
+        F: FnMut(Match) -> std::result::Result<ControlFlow<()>, E>,
+        E: From<Error>,
+    {
+        // the index is sorted by the verbatim names, so anything else has to be compared with every entry
+        if !self.matches_name_prefixes() {
+            return self.visit(index.entries().iter().map(|entry| &entry.path), f);
+        }
+        // verbatim terms fold into themselves, without their mode key
+        let mut ranges = self.terms.iter().map(|term| index.prefix_range(&term.folded)).collect::<Vec<_>>();
+        ranges.sort_by_key(|range| range.start);
+        // ranges of shorter terms contain the ranges of longer ones, so skip what was already visited
+        let mut next = 0;
+        for range in &mut ranges {
+            range.start = range.start.max(next);
+            next = next.max(range.end);
+        }
+        let entries = ranges.into_iter().filter(|range| !range.is_empty()).flat_map(|range| &index.entries()[range]);
+        self.visit(entries.map(|entry| &entry.path), f)
+    }
+
+    fn visit<P, F, E>(&self, paths: impl IntoIterator<Item = P>, mut f: F) -> std::result::Result<(), E>
+    where
+        P: AsRef<Path>,
+        F: FnMut(Match) -> std::result::Result<ControlFlow<()>, E>,
+        E: From<Error>,
+    {
+        let matches = paths.into_iter().filter_map(|path| self.match_path(path.as_ref()).transpose());
+        if self.options.mode == Mode::Fuzzy {
+            let mut matches = matches.collect::<Result<Vec<_>>>()?;
+            matches.sort_by_key(|m| Reverse(m.score));
+            for m in matches {
+                if f(m)?.is_break() {
+                    break;
+                }
+            }
+        } else {
+            for m in matches {
+                if f(m?)?.is_break() {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn find_all(&self) -> Result<Vec<Match>> {
+        let mut matches = Vec::new();
+        self.search(|m| {
+            if m.excluded_by.is_none() {
+                matches.push(m);
+            }
+            Ok::<_, Error>(ControlFlow::Continue(()))
+        })?;
+        Ok(matches)
+    }
+}
+
+// literal and glob terms expanded, without duplicates
+fn expand_all(terms: Vec<String>, options: &MatchOptions) -> Result<Vec<String>> {
+    if options.syntax == Syntax::Regex {
+        return Ok(terms);
+    }
+    let mut seen = HashSet::new();
+    let mut expanded = Vec::new();
+    for term in &terms {
+        expanded.extend(expand(term)?.into_iter().filter(|text| seen.insert(text.clone())));
+    }
+    Ok(expanded)
+}
+
+fn find_literal(mode: Mode, text: &str, term: &str) -> Option<Range<usize>> {
+    match mode {
+        Mode::Prefix => text.starts_with(term).then_some(0..term.len()),
+        Mode::Contains => text.find(term).map(|start| start..start + term.len()),
+        Mode::Suffix => text.ends_with(term).then(|| text.len() - term.len()..text.len()),
+        Mode::Exact => (text == term).then_some(0..text.len()),
+        Mode::Fuzzy => None,
+    }
+}
+
+// the key without the extension of the file, if it ends with it
+fn stem<'s>(key: &'s str, path: &Path) -> &'s str {
+    let Some(extension) = path.extension() else { return key };
+    key.strip_suffix(&*extension.to_string_lossy()).and_then(|key| key.strip_suffix('.')).unwrap_or(key)
+}
+
+// the match must not end in the middle of a word, so that ABC-1 doesn't find ABC-10
+fn is_boundary(key: &str, matched_len: usize) -> bool {
+    !key[matched_len..].chars().next().is_some_and(char::is_alphanumeric)
+}
+
+// joins ranges that touch or overlap, several folded chars may come from the same original ones
+fn merge_ranges(ranges: impl IntoIterator<Item = Range<usize>>) -> Vec<Range<usize>> {
+    let mut merged: Vec<Range<usize>> = Vec::new();
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
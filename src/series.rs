@@ -0,0 +1,72 @@
+use std::{collections::BTreeSet, ops::RangeInclusive};
+use crate::{Match, Result};
+
+/// The numbers following a prefix in file names, e.g. the episodes of a series.
+#[derive(Debug, Clone)]
+pub struct Series {
+    pub prefix: String,
+    pub numbers: BTreeSet<u64>,
+    // what separates the prefix from the numbers and the digits of the shortest number, so that missing numbers are
+    // named like the present ones
+    separator: Option<String>,
+    width: Option<usize>,
+}
+
+impl Series {
+    pub fn new(prefix: &str) -> Self {
+        Series { prefix: prefix.to_string(), numbers: BTreeSet::new(), separator: None, width: None }
+    }
+
+    /// Adds the number following the matched prefix, skipping separators between them. Returns whether the name has
+    /// one.
+    pub fn insert(&mut self, m: &Match) -> Result<bool> {
+        let filename = m.file_name()?;
+        let end = m.matched.last().map_or(0, |range| range.end);
+        let rest = &filename[end..];
+        let separator = &rest[..rest.find(char::is_alphanumeric).unwrap_or(rest.len())];
+        let rest = &rest[separator.len()..];
+        let digits = &rest[..rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len())];
+        let Ok(number) = digits.parse() else { return Ok(false) };
+        self.numbers.insert(number);
+        self.separator.get_or_insert_with(|| separator.to_string());
+        self.width = Some(self.width.map_or(digits.len(), |width| width.min(digits.len())));
+        Ok(true)
+    }
+
+    pub fn first(&self) -> Option<u64> {
+        self.numbers.first().copied()
+    }
+
+    pub fn last(&self) -> Option<u64> {
+        self.numbers.last().copied()
+    }
+
+    /// The runs of numbers missing between the first and last ones.
+    pub fn gaps(&self) -> Vec<RangeInclusive<u64>> {
+        let mut gaps = Vec::new();
+        let mut numbers = self.numbers.iter();
+        let Some(mut previous) = numbers.next().copied() else { return gaps };
+        for &number in numbers {
+            if number > previous + 1 {
+                gaps.push(previous + 1..=number - 1);
+            }
+            previous = number;
+        }
+        gaps
+    }
+
+    /// The name of the number, e.g. `ABC-007`.
+    pub fn name(&self, number: u64) -> String {
+        let width = self.width.unwrap_or(0);
+        format!("{}{}{number:0width$}", self.prefix, self.separator.as_deref().unwrap_or_default())
+    }
+
+    /// The names of the numbers in the range, as a search term, e.g. `ABC-100..120`.
+    pub fn range_name(&self, range: &RangeInclusive<u64>) -> String {
+        if range.start() == range.end() {
+            return self.name(*range.start());
+        }
+        let width = self.width.unwrap_or(0);
+        format!("{}..{:0width$}", self.name(*range.start()), range.end())
+    }
+}
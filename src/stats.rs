@@ -0,0 +1,67 @@
+use std::{cmp::Reverse, collections::{HashMap, HashSet}};
+use crate::{complete::next_token_len, file_name, CategoryConfig, Result, Walker};
+
+/// How the files of a category are named and where they are.
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    /// The most common prefixes made of whole tokens, most files first, each with its number of files. A prefix is
+    /// left out if a longer one is shared by the same files.
+    pub prefixes: Vec<(String, usize)>,
+    pub dirs: Vec<DirStats>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DirStats {
+    pub dir: String,
+    pub n_files: usize,
+    pub size: u64,
+}
+
+impl Stats {
+    /// Walks the dirs of the category and keeps the `n_prefixes` most common prefixes.
+    pub fn collect(category: &CategoryConfig, n_prefixes: usize) -> Result<Self> {
+        // number of files and length of the prefix one token shorter
+        let mut counts = HashMap::<String, (usize, usize)>::new();
+        let mut dirs = category.dirs.iter().map(|dir| DirStats { dir: dir.clone(), ..Default::default() }).collect::<Vec<_>>();
+        // the paths come dir by dir
+        let mut dir = 0;
+        for path in Walker::new(category)?.walk() {
+            let metadata = match path.metadata() {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    log::warn!("Skipped {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            if !metadata.is_file() {
+                continue;
+            }
+            while !path.starts_with(&dirs[dir].dir) {
+                dir += 1;
+            }
+            dirs[dir].n_files += 1;
+            dirs[dir].size += metadata.len();
+
+            // every prefix short of the whole name
+            let filename = file_name(&path)?;
+            let (mut parent_len, mut end) = (0, next_token_len(&filename));
+            while end < filename.len() {
+                counts.entry(filename[..end].to_string()).or_insert((0, parent_len)).0 += 1;
+                (parent_len, end) = (end, end + next_token_len(&filename[end..]));
+            }
+        }
+
+        // a prefix shared by the same files as a longer one tells less
+        let shared_further = counts.iter()
+            .filter(|(prefix, &(count, parent_len))| parent_len > 0 && counts[&prefix[..parent_len]].0 == count)
+            .map(|(prefix, &(_, parent_len))| &prefix[..parent_len])
+            .collect::<HashSet<_>>();
+        let mut prefixes = counts.iter()
+            .filter(|&(prefix, &(count, _))| count > 1 && !shared_further.contains(prefix.as_str()))
+            .map(|(prefix, &(count, _))| (prefix.clone(), count))
+            .collect::<Vec<_>>();
+        prefixes.sort_by_key(|(prefix, count)| (Reverse(*count), prefix.clone()));
+        prefixes.truncate(n_prefixes);
+        Ok(Stats { prefixes, dirs })
+    }
+}
@@ -0,0 +1,37 @@
+use regex::Regex;
+use crate::Result;
+
+const BUILTIN_RULES: [(&str, &str); 3] = [
+    // [Group], (2024), 【tag】, {tag}
+    ("brackets", r"(?:\[[^\]]*\]|\([^)]*\)|【[^】]*】|\{[^}]*\})\s*"),
+    // 2024-05-01_, 20240501
+    ("date", r"\d{4}[-_.]?\d{2}[-_.]?\d{2}(?:[\s_.]+|-+)"),
+    // 01 - , 01. , 01
+    ("track", r"\d{1,3}(?:\s*-\s*|\.\s*|\s+)"),
+];
+
+/// Leading noise removed from file names before they are compared with the terms.
+#[derive(Debug, Clone, Default)]
+pub struct StripRules {
+    rules: Vec<Regex>,
+}
+
+impl StripRules {
+    /// Each rule is the name of a builtin rule (`brackets`, `date` or `track`) or a regex.
+    pub fn new(rules: &[String]) -> Result<Self> {
+        let rules = rules.iter().map(|rule| {
+            let pattern = BUILTIN_RULES.iter().find(|(name, _)| name == rule).map_or(rule.as_str(), |(_, pattern)| pattern);
+            Ok(Regex::new(&format!(r"\A(?:{pattern})"))?)
+        }).collect::<Result<_>>()?;
+        Ok(StripRules { rules })
+    }
+
+    /// Byte length of the noise at the start of the file name, the rules are applied as long as any of them matches.
+    pub fn noise_len(&self, filename: &str) -> usize {
+        let mut len = 0;
+        while let Some(m) = self.rules.iter().filter_map(|rule| rule.find(&filename[len..])).find(|m| !m.is_empty()) {
+            len += m.end();
+        }
+        len
+    }
+}
@@ -0,0 +1,711 @@
+use std::{collections::{BTreeMap, HashMap, VecDeque}, fs::{self, Metadata}, mem, os::unix::fs::MetadataExt, path::{Path, PathBuf}, sync::{atomic::{AtomicBool, AtomicUsize, Ordering}, Arc, Condvar, Mutex, MutexGuard}, thread, vec};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use crate::{pattern::path_glob_to_regex, CategoryConfig, Result};
+
+/// Name of the files listing, in gitignore syntax, what isn't walked in the dir they're in.
+pub const IGNORE_FILE_NAME: &str = ".prefixsearchignore";
+
+// how many paths all threads together walk ahead of the ones being read, how many of the unit being read the thread
+// walking it does, and how many they pass on at once
+const BUFFER_LEN: usize = if cfg!(test) { 1 << 10 } else { 1 << 16 };
+const CURRENT_LEN: usize = if cfg!(test) { 1 << 8 } else { 1 << 12 };
+const BATCH_LEN: usize = 256;
+
+/// How the dirs of a category are walked, settable per category and per search.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct WalkOptions {
+    /// Paths not walked into, in gitignore syntax relative to each dir of the category, e.g. `@eaDir` or
+    /// `/.Trash-*`.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// How deep to go, 1 being the entries of the category dirs themselves.
+    #[serde(default)]
+    pub max_depth: Option<usize>,
+    /// Walk into symlinked dirs, skipping the ones that link back to a dir being walked.
+    #[serde(default)]
+    pub follow_symlinks: bool,
+    /// Also walk files and dirs whose names start with a dot.
+    #[serde(default = "WalkOptions::default_include_hidden")]
+    pub include_hidden: bool,
+    /// Don't walk into dirs on other filesystems than the category dir.
+    #[serde(default)]
+    pub one_file_system: bool,
+    /// How many threads walk the dirs, one per CPU by default. The paths come in the same order whatever the number.
+    #[serde(default)]
+    pub threads: Option<usize>,
+}
+
+impl Default for WalkOptions {
+    fn default() -> Self {
+        WalkOptions { exclude: Vec::new(), max_depth: None, follow_symlinks: false, include_hidden: Self::default_include_hidden(), one_file_system: false, threads: None }
+    }
+}
+
+impl WalkOptions {
+    /// Whether both walk the same paths, whatever their number of threads.
+    pub fn walks_same(&self, other: &Self) -> bool {
+        *self == WalkOptions { threads: self.threads, ..other.clone() }
+    }
+
+    fn default_include_hidden() -> bool {
+        true
+    }
+}
+
+/// Walks the dirs of a category, pruning the subtrees excluded by the category or by ignore files.
+#[derive(Debug, Clone)]
+pub struct Walker {
+    dirs: Vec<PathBuf>,
+    options: WalkOptions,
+    // the exclude globs of the category, relative to each of its dirs
+    exclude: Vec<Rule>,
+}
+
+impl Walker {
+    pub fn new(category: &CategoryConfig) -> Result<Self> {
+        let exclude = category.walk.exclude.iter().filter_map(|line| Rule::parse(line).transpose()).collect::<Result<_>>()?;
+        Ok(Walker { dirs: category.dirs.iter().map(PathBuf::from).collect(), options: category.walk.clone(), exclude })
+    }
+
+    /// Every path under the dirs of the category, dir by dir. The entries of each dir are sorted by name.
+    pub fn walk(&self) -> Box<dyn Iterator<Item = PathBuf> + Send + '_> {
+        let threads = self.options.threads.unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+        if threads > 1 {
+            Box::new(self.walk_parallel(threads))
+        } else {
+            Box::new(self.dirs.iter().flat_map(|dir| self.walk_dir(dir)))
+        }
+    }
+
+    /// Every path under the dir, which should be one of the dirs of the category.
+    pub fn walk_dir(&self, dir: &Path) -> Walk<'_> {
+        log::debug!("Walking {}", dir.display());
+        let mut walk = Walk::new(self, dir);
+        match self.metadata(dir) {
+            Ok(metadata) => walk.push_dir(dir, &metadata, 0),
+            Err(e) => log::warn!("Could not read {}: {}", dir.display(), e),
+        }
+        walk
+    }
+
+    /// The path and everything under it, unless it's excluded or ignored. Ignore files of the dirs between the
+    /// category dir and the path apply too.
+    pub fn walk_path(&self, path: &Path) -> Walk<'_> {
+        let Some(root) = self.dirs.iter().find(|dir| path.starts_with(dir)) else {
+            let mut walk = Walk::new(self, Path::new(""));
+            walk.stack.push(Frame { entries: vec![path.to_path_buf()].into_iter(), rules: None, depth: 0, id: None });
+            return walk;
+        };
+        let mut walk = Walk::new(self, root);
+        let mut dirs = path.ancestors().skip(1).take_while(|dir| dir.starts_with(root)).collect::<Vec<_>>();
+        dirs.reverse();
+        for (depth, dir) in dirs.iter().enumerate() {
+            let metadata = self.metadata(dir).ok().filter(|metadata| {
+                (depth == 0 || (walk.is_visible(dir) && !walk.is_ignored(dir, true))) && walk.enters(dir, metadata, depth)
+            });
+            let Some(metadata) = metadata else {
+                walk.stack.clear();
+                return walk;
+            };
+            let id = Some((metadata.dev(), metadata.ino()));
+            walk.stack.push(Frame { entries: Vec::new().into_iter(), rules: read_ignore_file(dir).map(Arc::new), depth, id });
+        }
+        if let Some(frame) = walk.stack.last_mut() {
+            frame.entries = vec![path.to_path_buf()].into_iter();
+        }
+        walk
+    }
+
+    // each dir of the category is walked by one thread, which leaves the dirs it comes across to the idle ones
+    fn walk_parallel(&self, threads: usize) -> ParallelWalk {
+        let mut state = State { n_units: self.dirs.len(), ..Default::default() };
+        for (root, dir) in self.dirs.iter().enumerate() {
+            log::debug!("Walking {} with {} threads", dir.display(), threads);
+            state.outputs.insert(root, Output::default());
+            match self.metadata(dir) {
+                Ok(metadata) => {
+                    let device = Walk::new(self, dir).device;
+                    let unit = Unit { id: root, root, dir: dir.clone(), metadata, depth: 0, device, ancestors: Vec::new() };
+                    state.queue.insert((root, dir.clone()), unit);
+                }
+                Err(e) => {
+                    log::warn!("Could not read {}: {}", dir.display(), e);
+                    state.outputs.insert(root, Output { items: Vec::new(), done: true });
+                }
+            }
+        }
+        let shared = Arc::new(SharedWalk { walker: self.clone(), state: Mutex::new(state), changed: Condvar::new(), current: AtomicUsize::new(0), starving: AtomicBool::new(false), n_idle: AtomicUsize::new(0) });
+        for _ in 0..threads {
+            let shared = shared.clone();
+            thread::spawn(move || shared.work());
+        }
+        let items = (0..self.dirs.len()).map(Item::Unit).collect();
+        ParallelWalk { shared, unit: None, items, outer: Vec::new() }
+    }
+
+    fn metadata(&self, path: &Path) -> std::io::Result<Metadata> {
+        if self.options.follow_symlinks {
+            fs::metadata(path)
+        } else {
+            fs::symlink_metadata(path)
+        }
+    }
+}
+
+/// Iterator over the paths of a walk, reading dirs as it goes.
+pub struct Walk<'w> {
+    walker: &'w Walker,
+    root: PathBuf,
+    // the filesystem of the root, with `one_file_system`
+    device: Option<u64>,
+    // the dirs being walked, innermost last
+    stack: Vec<Frame>,
+}
+
+#[derive(Clone)]
+struct Frame {
+    // entries of the dir not visited yet
+    entries: vec::IntoIter<PathBuf>,
+    // what the ignore file of the dir says
+    rules: Option<Arc<Rules>>,
+    // of the dir, the root being 0
+    depth: usize,
+    // device and inode of the dir, to notice symlink loops
+    id: Option<(u64, u64)>,
+}
+
+impl<'w> Walk<'w> {
+    fn new(walker: &'w Walker, root: &Path) -> Self {
+        let device = walker.options.one_file_system.then(|| fs::metadata(root).ok()).flatten().map(|metadata| metadata.dev());
+        Walk { walker, root: root.to_path_buf(), device, stack: Vec::new() }
+    }
+
+    fn push_dir(&mut self, dir: &Path, metadata: &Metadata, depth: usize) {
+        let entries = read_sorted_dir(dir).unwrap_or_else(|e| {
+            log::warn!("Could not read {}: {}", dir.display(), e);
+            Vec::new()
+        });
+        let id = Some((metadata.dev(), metadata.ino()));
+        self.stack.push(Frame { entries: entries.into_iter(), rules: read_ignore_file(dir).map(Arc::new), depth, id });
+    }
+
+    // whether the entries of the dir are walked too
+    fn enters(&self, dir: &Path, metadata: &Metadata, depth: usize) -> bool {
+        if self.walker.options.max_depth.is_some_and(|max_depth| depth >= max_depth) {
+            return false;
+        }
+        if self.device.is_some_and(|device| device != metadata.dev()) {
+            log::debug!("Not crossing into the filesystem of {}", dir.display());
+            return false;
+        }
+        let id = Some((metadata.dev(), metadata.ino()));
+        if self.stack.iter().any(|frame| frame.id == id) {
+            log::warn!("Skipped {}, it links back to a dir being walked", dir.display());
+            return false;
+        }
+        true
+    }
+
+    fn is_visible(&self, path: &Path) -> bool {
+        self.walker.options.include_hidden || !path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.'))
+    }
+
+    // the innermost ignore file with a rule for the path decides, the exclude globs of the category come last
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let from_files = self.stack.iter().rev().filter_map(|frame| frame.rules.as_ref()).find_map(|rules| rules.ignores(path, is_dir));
+        from_files.or_else(|| Rules::ignores_in(&self.walker.exclude, &self.root, path, is_dir)).unwrap_or(false)
+    }
+
+    // the next path, and what `split` returned if the path is a dir it took over instead of it being walked here
+    fn advance<T>(&mut self, mut split: impl FnMut(&Self, &Path, &Metadata, usize) -> Option<T>) -> Option<(PathBuf, Option<T>)> {
+        loop {
+            let frame = self.stack.last_mut()?;
+            let Some(path) = frame.entries.next() else {
+                self.stack.pop();
+                continue;
+            };
+            let depth = frame.depth + 1;
+            if !self.is_visible(&path) {
+                continue;
+            }
+            let metadata = match self.walker.metadata(&path) {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    log::warn!("Could not read {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            if self.is_ignored(&path, metadata.is_dir()) {
+                log::debug!("Ignored {}", path.display());
+                continue;
+            }
+            if metadata.is_dir() && self.enters(&path, &metadata, depth) {
+                if let Some(split) = split(self, &path, &metadata, depth) {
+                    return Some((path, Some(split)));
+                }
+                self.push_dir(&path, &metadata, depth);
+            }
+            return Some((path, None));
+        }
+    }
+}
+
+impl Iterator for Walk<'_> {
+    type Item = PathBuf;
+
+    fn next(&mut self) -> Option<PathBuf> {
+        self.advance(|_, _, _, _| None::<()>).map(|(path, _)| path)
+    }
+}
+
+// iterator over the paths of a parallel walk, dropping it stops the threads
+struct ParallelWalk {
+    shared: Arc<SharedWalk>,
+    // the unit being read, `None` before and after the dirs of the category, and what was taken of it
+    unit: Option<usize>,
+    items: VecDeque<Item>,
+    // the units it is in, and what is left of them
+    outer: Vec<(Option<usize>, VecDeque<Item>)>,
+}
+
+struct SharedWalk {
+    walker: Walker,
+    state: Mutex<State>,
+    changed: Condvar,
+    // the unit being read, and whether the reader waits for its paths
+    current: AtomicUsize,
+    starving: AtomicBool,
+    // threads waiting for a unit to walk
+    n_idle: AtomicUsize,
+}
+
+#[derive(Default)]
+struct State {
+    // units no thread walks yet, in the order their paths come
+    queue: BTreeMap<(usize, PathBuf), Unit>,
+    // what was walked of the units and not read yet
+    outputs: HashMap<usize, Output>,
+    n_units: usize,
+    n_buffered: usize,
+    n_walking: usize,
+    stopped: bool,
+}
+
+// a dir and everything under it
+struct Unit {
+    id: usize,
+    // index of the dir of the category it's in
+    root: usize,
+    dir: PathBuf,
+    metadata: Metadata,
+    depth: usize,
+    device: Option<u64>,
+    // the dirs it's in, without their entries
+    ancestors: Vec<Frame>,
+}
+
+#[derive(Default)]
+struct Output {
+    items: Vec<Item>,
+    done: bool,
+}
+
+enum Item {
+    Path(PathBuf),
+    // the paths of the unit come next
+    Unit(usize),
+}
+
+impl SharedWalk {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn wait<'s>(&self, state: MutexGuard<'s, State>) -> MutexGuard<'s, State> {
+        self.changed.wait(state).unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn work(&self) {
+        let mut state = self.lock();
+        loop {
+            if state.stopped {
+                return;
+            }
+            if let Some((_, unit)) = state.queue.pop_first() {
+                state = self.walk_unit(state, unit);
+                continue;
+            }
+            // the units being walked may still leave dirs to this thread
+            if state.n_walking == 0 {
+                return;
+            }
+            self.n_idle.fetch_add(1, Ordering::Relaxed);
+            state = self.wait(state);
+            self.n_idle.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    fn walk_unit<'s>(&'s self, mut state: MutexGuard<'s, State>, unit: Unit) -> MutexGuard<'s, State> {
+        state.n_walking += 1;
+        drop(state);
+        let root = &self.walker.dirs[unit.root];
+        let mut walk = Walk { walker: &self.walker, root: root.clone(), device: unit.device, stack: unit.ancestors };
+        walk.push_dir(&unit.dir, &unit.metadata, unit.depth);
+        let mut batch = Vec::new();
+        loop {
+            let next = walk.advance(|walk, dir, metadata, depth| self.split(walk, unit.root, dir, metadata, depth));
+            let done = next.is_none();
+            if let Some((path, split)) = next {
+                batch.push(Item::Path(path));
+                batch.extend(split.map(Item::Unit));
+            }
+            // the paths of the unit being read are passed on right away if they're waited for
+            let waited_for = self.starving.load(Ordering::Relaxed) && self.current.load(Ordering::Relaxed) == unit.id;
+            let ready = done || batch.len() >= BATCH_LEN || waited_for;
+            if ready && (!self.send(unit.id, &mut batch, done) || done) {
+                break;
+            }
+        }
+        let mut state = self.lock();
+        state.n_walking -= 1;
+        self.changed.notify_all();
+        state
+    }
+
+    // leaves the dir to an idle thread if there is one
+    fn split(&self, walk: &Walk, root: usize, dir: &Path, metadata: &Metadata, depth: usize) -> Option<usize> {
+        if self.n_idle.load(Ordering::Relaxed) == 0 {
+            return None;
+        }
+        let mut state = self.lock();
+        if state.stopped || self.n_idle.load(Ordering::Relaxed) <= state.queue.len() {
+            return None;
+        }
+        log::debug!("Walking {} on another thread", dir.display());
+        let id = state.n_units;
+        state.n_units += 1;
+        state.outputs.insert(id, Output::default());
+        let ancestors = walk.stack.iter().map(|frame| Frame { entries: Vec::new().into_iter(), ..frame.clone() }).collect();
+        let unit = Unit { id, root, dir: dir.to_path_buf(), metadata: metadata.clone(), depth, device: walk.device, ancestors };
+        state.queue.insert((root, dir.to_path_buf()), unit);
+        self.changed.notify_all();
+        Some(id)
+    }
+
+    // false once the walk is stopped
+    fn send(&self, unit: usize, batch: &mut Vec<Item>, done: bool) -> bool {
+        let mut state = self.lock();
+        loop {
+            if state.stopped {
+                return false;
+            }
+            let current = self.current.load(Ordering::Relaxed);
+            if current == unit {
+                // the reader takes whatever there is of the unit it reads, so only that counts
+                if state.outputs.get(&unit).is_none_or(|output| output.items.len() < CURRENT_LEN) {
+                    break;
+                }
+                state = self.wait(state);
+            } else if state.n_buffered >= BUFFER_LEN {
+                // the unit being read may wait for a thread, and all of them for it to be read
+                let key = state.queue.iter().find(|(_, unit)| unit.id == current).map(|(key, _)| key.clone());
+                state = match key.and_then(|key| state.queue.remove(&key)) {
+                    Some(current) => self.walk_unit(state, current),
+                    None => self.wait(state),
+                };
+            } else {
+                break;
+            }
+        }
+        state.n_buffered += batch.len();
+        let output = state.outputs.entry(unit).or_default();
+        output.items.append(batch);
+        output.done = done;
+        self.changed.notify_all();
+        true
+    }
+
+    fn read(&self, unit: usize) -> Option<Vec<Item>> {
+        let mut state = self.lock();
+        loop {
+            let output = state.outputs.entry(unit).or_default();
+            if !output.items.is_empty() {
+                let items = mem::take(&mut output.items);
+                state.n_buffered -= items.len();
+                self.changed.notify_all();
+                return Some(items);
+            }
+            if output.done {
+                state.outputs.remove(&unit);
+                return None;
+            }
+            self.starving.store(true, Ordering::Relaxed);
+            state = self.wait(state);
+            self.starving.store(false, Ordering::Relaxed);
+        }
+    }
+
+    fn set_current(&self, unit: usize) {
+        let _state = self.lock();
+        self.current.store(unit, Ordering::Relaxed);
+        self.changed.notify_all();
+    }
+}
+
+impl Iterator for ParallelWalk {
+    type Item = PathBuf;
+
+    fn next(&mut self) -> Option<PathBuf> {
+        loop {
+            match self.items.pop_front() {
+                Some(Item::Path(path)) => return Some(path),
+                Some(Item::Unit(unit)) => {
+                    self.outer.push((self.unit, mem::take(&mut self.items)));
+                    self.unit = Some(unit);
+                    self.shared.set_current(unit);
+                }
+                None => {
+                    if let Some(items) = self.unit.and_then(|unit| self.shared.read(unit)) {
+                        self.items = items.into();
+                        continue;
+                    }
+                    (self.unit, self.items) = self.outer.pop()?;
+                    if let Some(unit) = self.unit {
+                        self.shared.set_current(unit);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+impl ParallelWalk {
+    fn n_buffered(&self) -> usize {
+        self.shared.lock().n_buffered
+    }
+}
+
+impl Drop for ParallelWalk {
+    fn drop(&mut self) {
+        self.shared.lock().stopped = true;
+        self.shared.changed.notify_all();
+    }
+}
+
+fn read_sorted_dir(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut entries = fs::read_dir(dir)?.map(|entry| Ok(entry?.path())).collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort();
+    Ok(entries)
+}
+
+// the rules of an ignore file, relative to the dir it's in
+#[derive(Debug, Clone)]
+struct Rules {
+    dir: PathBuf,
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    regex: Regex,
+    negated: bool,
+    dir_only: bool,
+}
+
+impl Rules {
+    // `Some(true)` if the last rule matching the path ignores it, `Some(false)` if it keeps it
+    fn ignores(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        Self::ignores_in(&self.rules, &self.dir, path, is_dir)
+    }
+
+    fn ignores_in(rules: &[Rule], dir: &Path, path: &Path, is_dir: bool) -> Option<bool> {
+        let relative = path.strip_prefix(dir).ok()?.to_string_lossy();
+        rules.iter().rev().find(|rule| (is_dir || !rule.dir_only) && rule.regex.is_match(&relative)).map(|rule| !rule.negated)
+    }
+}
+
+impl Rule {
+    // `None` for blank lines and comments
+    fn parse(line: &str) -> Result<Option<Self>> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None);
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(line) => (true, line),
+            None => (false, line.strip_prefix('\\').filter(|line| line.starts_with(['#', '!'])).unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        // globs with a slash are relative to the dir of the rule, the others match names at any depth
+        let regex = match line.strip_prefix('/') {
+            Some(line) => format!("^{}$", path_glob_to_regex(line)),
+            None if line.contains('/') => format!("^{}$", path_glob_to_regex(line)),
+            None => format!("^(?:.*/)?{}$", path_glob_to_regex(line)),
+        };
+        Ok(Some(Rule { regex: Regex::new(&regex)?, negated, dir_only }))
+    }
+}
+
+fn read_ignore_file(dir: &Path) -> Option<Rules> {
+    let path = dir.join(IGNORE_FILE_NAME);
+    let content = fs::read_to_string(&path).ok()?;
+    let rules = content.lines().filter_map(|line| match Rule::parse(line) {
+        Ok(rule) => rule,
+        Err(e) => {
+            log::warn!("Skipped rule {} in {}: {}", line, path.display(), e);
+            None
+        }
+    }).collect();
+    Some(Rules { dir: dir.to_path_buf(), rules })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(lines: &[&str]) -> Rules {
+        let rules = lines.iter().filter_map(|line| Rule::parse(line).unwrap()).collect();
+        Rules { dir: PathBuf::from("/root"), rules }
+    }
+
+    fn ignores(rules: &Rules, path: &str, is_dir: bool) -> Option<bool> {
+        rules.ignores(&Path::new("/root").join(path), is_dir)
+    }
+
+    #[test]
+    fn comments_and_blank_lines() {
+        assert!(Rule::parse("# comment").unwrap().is_none());
+        assert!(Rule::parse("   ").unwrap().is_none());
+        let rules = rules(&[r"\#file", r"\!important"]);
+        assert_eq!(ignores(&rules, "#file", false), Some(true));
+        assert_eq!(ignores(&rules, "!important", false), Some(true));
+        assert_eq!(ignores(&rules, "important", false), None);
+    }
+
+    #[test]
+    fn names_match_at_any_depth() {
+        let rules = rules(&["@eaDir"]);
+        assert_eq!(ignores(&rules, "@eaDir", true), Some(true));
+        assert_eq!(ignores(&rules, "a/b/@eaDir", true), Some(true));
+        assert_eq!(ignores(&rules, "a/@eaDir.mp4", false), None);
+        assert_eq!(ignores(&rules, "x@eaDir", true), None);
+    }
+
+    #[test]
+    fn anchored_dir_only() {
+        let rules = rules(&["/.Trash-*/"]);
+        assert_eq!(ignores(&rules, ".Trash-1000", true), Some(true));
+        assert_eq!(ignores(&rules, ".Trash-1000", false), None);
+        assert_eq!(ignores(&rules, "a/.Trash-1000", true), None);
+        assert_eq!(ignores(&rules, ".Trash-1000/x", true), None);
+    }
+
+    #[test]
+    fn globs_with_slashes_are_relative() {
+        let rules = rules(&["a/*.nfo"]);
+        assert_eq!(ignores(&rules, "a/x.nfo", false), Some(true));
+        assert_eq!(ignores(&rules, "a/b/x.nfo", false), None);
+        assert_eq!(ignores(&rules, "b/a/x.nfo", false), None);
+    }
+
+    #[test]
+    fn double_stars() {
+        let rules = rules(&["a/**/b"]);
+        assert_eq!(ignores(&rules, "a/b", true), Some(true));
+        assert_eq!(ignores(&rules, "a/x/b", true), Some(true));
+        assert_eq!(ignores(&rules, "a/x/y/b", false), Some(true));
+        assert_eq!(ignores(&rules, "a/xb", false), None);
+        let rules = self::rules(&["**/tmp", "logs/**"]);
+        assert_eq!(ignores(&rules, "tmp", true), Some(true));
+        assert_eq!(ignores(&rules, "x/y/tmp", true), Some(true));
+        assert_eq!(ignores(&rules, "logs/a/b.log", false), Some(true));
+    }
+
+    #[test]
+    fn last_matching_rule_wins() {
+        let rules = rules(&["*.jpg", "!keep*.jpg"]);
+        assert_eq!(ignores(&rules, "a.jpg", false), Some(true));
+        assert_eq!(ignores(&rules, "keep.jpg", false), Some(false));
+        assert_eq!(ignores(&rules, "a.mp4", false), None);
+        let rules = self::rules(&["!keep", "*"]);
+        assert_eq!(ignores(&rules, "keep", false), Some(true));
+    }
+
+    #[test]
+    fn classes() {
+        let rules = rules(&["EP[!0-9]*", "[ab].txt"]);
+        assert_eq!(ignores(&rules, "EPX", false), Some(true));
+        assert_eq!(ignores(&rules, "EP1", false), None);
+        assert_eq!(ignores(&rules, "a.txt", false), Some(true));
+        assert_eq!(ignores(&rules, "c.txt", false), None);
+    }
+
+    #[test]
+    fn threads_dont_change_the_walk() {
+        let options = WalkOptions::default();
+        assert!(WalkOptions { threads: Some(4), ..Default::default() }.walks_same(&options));
+        assert!(options.walks_same(&WalkOptions { threads: Some(1), ..Default::default() }));
+        assert!(!WalkOptions { max_depth: Some(1), threads: Some(4), ..Default::default() }.walks_same(&options));
+    }
+
+    #[test]
+    fn parallel_walks_keep_the_order() {
+        let root = std::env::temp_dir().join(format!("prefix-search-walk-{}", std::process::id()));
+        for i in 0..20 {
+            let dir = root.join(format!("d{}", i % 4)).join(format!("e{}", i % 3)).join(format!("f{i}"));
+            fs::create_dir_all(&dir).unwrap();
+            for j in 0..30 {
+                fs::write(dir.join(format!("{j}.mp4")), "").unwrap();
+            }
+        }
+        fs::write(root.join("d1").join(IGNORE_FILE_NAME), "e2\n!e2/f5\n").unwrap();
+        let walk = |threads, max_depth| {
+            let walk = WalkOptions { threads: Some(threads), max_depth, exclude: vec!["/d3/e0/".to_string()], ..Default::default() };
+            let dirs = vec![root.join("d0").to_string_lossy().into_owned(), root.to_string_lossy().into_owned()];
+            let category = CategoryConfig { dirs, options: Default::default(), walk };
+            Walker::new(&category).unwrap().walk().collect::<Vec<_>>()
+        };
+        let (paths, shallow) = (walk(1, None), walk(1, Some(2)));
+        assert!(paths.contains(&root.join("d0/e0/f0/0.mp4")));
+        assert!(!paths.iter().any(|path| path.starts_with(root.join("d1/e2")) || path.starts_with(root.join("d3/e0"))));
+        for threads in [2, 3, 8] {
+            assert_eq!(walk(threads, None), paths);
+            assert_eq!(walk(threads, Some(2)), shallow);
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn stalled_readers_bound_the_buffer() {
+        let root = std::env::temp_dir().join(format!("prefix-search-stall-{}", std::process::id()));
+        for dir in ["flat", "tree/a", "tree/b", "tree/c"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for i in 0..2 * BUFFER_LEN {
+            fs::write(root.join("flat").join(i.to_string()), "").unwrap();
+            fs::write(root.join("tree").join(["a", "b", "c"][i % 3]).join(i.to_string()), "").unwrap();
+        }
+        // only the thread walking the unit being read passes on paths without the budget
+        for (dir, max_buffered) in [("flat", CURRENT_LEN + BATCH_LEN), ("tree", CURRENT_LEN + BUFFER_LEN + 4 * (BATCH_LEN + 1))] {
+            let category = CategoryConfig { dirs: vec![root.join(dir).to_string_lossy().into_owned()], options: Default::default(), walk: WalkOptions::default() };
+            let walker = Walker::new(&category).unwrap();
+            let mut walk = walker.walk_parallel(4);
+            assert!(walk.next().is_some());
+            std::thread::sleep(std::time::Duration::from_millis(500));
+            assert!(walk.n_buffered() <= max_buffered, "{} paths of {dir} buffered", walk.n_buffered());
+            assert_eq!(walk.count() + 1, walker.walk_dir(&root.join(dir)).count());
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn paths_outside_the_dir() {
+        let rules = rules(&["*"]);
+        assert_eq!(rules.ignores(Path::new("/other/a"), false), None);
+    }
+}
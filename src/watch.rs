@@ -0,0 +1,111 @@
+use std::{collections::{HashMap, HashSet}, path::{Path, PathBuf}, sync::mpsc::{channel, Receiver, RecvTimeoutError}, time::Duration};
+use notify::{event::{ModifyKind, RenameMode}, EventKind, RecommendedWatcher, RecursiveMode, Watcher as _};
+use crate::{CategoryConfig, Config, Error, Index, Result};
+
+// events arriving within this delay of each other are applied as one batch
+const BATCH_DELAY: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Added(PathBuf),
+    Removed(PathBuf),
+    /// Events were dropped, the whole category has to be walked again.
+    Rescan,
+}
+
+impl Change {
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Change::Added(path) | Change::Removed(path) => Some(path),
+            Change::Rescan => None,
+        }
+    }
+}
+
+/// Subscribes to filesystem events on the dirs of the categories.
+pub struct Watcher {
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<notify::Event>>,
+    roots: Vec<(PathBuf, String)>,
+}
+
+impl Watcher {
+    pub fn new<'a>(categories: impl IntoIterator<Item = (&'a str, &'a CategoryConfig)>) -> Result<Self> {
+        let (tx, events) = channel();
+        let mut watcher = notify::recommended_watcher(tx)?;
+        let mut roots = Vec::new();
+        for (name, category) in categories {
+            for dir in &category.dirs {
+                log::debug!("Watching dir: {}", dir);
+                watcher.watch(Path::new(dir), RecursiveMode::Recursive)?;
+                roots.push((PathBuf::from(dir), name.to_string()));
+            }
+        }
+        Ok(Watcher { _watcher: watcher, events, roots })
+    }
+
+    /// Blocks until something changes, and returns the changes per category name.
+    pub fn recv(&self) -> Result<Vec<(String, Change)>> {
+        let mut changes = Vec::new();
+        let mut event = self.events.recv().map_err(|_| Error::WatcherStopped)?;
+        loop {
+            match event {
+                Ok(event) => self.collect_changes(event, &mut changes),
+                Err(e) => log::warn!("Watch error: {}", e),
+            }
+            event = match self.events.recv_timeout(BATCH_DELAY) {
+                Ok(event) => event,
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return Err(Error::WatcherStopped),
+            };
+        }
+        Ok(changes)
+    }
+
+    fn collect_changes(&self, event: notify::Event, changes: &mut Vec<(String, Change)>) {
+        log::debug!("Event: {:?}", event);
+        if event.need_rescan() {
+            for (_, name) in &self.roots {
+                let change = (name.clone(), Change::Rescan);
+                if !changes.contains(&change) {
+                    changes.push(change);
+                }
+            }
+            return;
+        }
+        let path_changes = match (event.kind, event.paths.as_slice()) {
+            (EventKind::Create(_), paths) => paths.iter().map(|path| Change::Added(path.clone())).collect(),
+            (EventKind::Remove(_), paths) => paths.iter().map(|path| Change::Removed(path.clone())).collect(),
+            (EventKind::Modify(ModifyKind::Name(RenameMode::From)), [from]) => vec![Change::Removed(from.clone())],
+            (EventKind::Modify(ModifyKind::Name(RenameMode::To)), [to]) => vec![Change::Added(to.clone())],
+            (EventKind::Modify(ModifyKind::Name(RenameMode::Both)), [from, to]) => {
+                vec![Change::Removed(from.clone()), Change::Added(to.clone())]
+            }
+            // the backend couldn't tell which side of the rename the path is
+            (EventKind::Modify(ModifyKind::Name(_)), paths) => paths.iter().map(|path| {
+                if path.exists() { Change::Added(path.clone()) } else { Change::Removed(path.clone()) }
+            }).collect(),
+            _ => Vec::new(),
+        };
+        for change in path_changes {
+            let Some(path) = change.path() else { continue };
+            for (root, name) in &self.roots {
+                if path.starts_with(root) && path != root {
+                    changes.push((name.clone(), change.clone()));
+                }
+            }
+        }
+    }
+}
+
+/// Applies the changes to the indexes of their categories, and returns the names of the categories whose index changed.
+pub fn apply_changes(changes: Vec<(String, Change)>, indexes: &mut HashMap<String, Index>, config: &Config) -> Result<HashSet<String>> {
+    let mut changed = HashSet::new();
+    for (name, change) in changes {
+        let Some(index) = indexes.get_mut(&name) else { continue };
+        if index.apply(&change, config.category(&name)?)? {
+            changed.insert(name);
+        }
+    }
+    Ok(changed)
+}